@@ -1,5 +1,6 @@
 // src/cli.rs
 use clap::{Parser, ValueEnum};
+use utils::coordinate::CoordinateSystem;
 /// A CLI tool that processes a file with optional numeric parameters.
 #[derive(Parser, Debug)]
 #[command(name = "memopair", version, about = "Methylation Motif Pairs")]
@@ -18,7 +19,7 @@ pub struct Cli {
 
     #[arg(
         value_name = "MOTIFS",
-        help = "Comeplement motif pairs in the format: 'MOTIF_TYPE1_POS1_TYPE2_POS2', e.g. 'ACGT_a_0_m_3' or 'CCWGG_4mC_0_5mC_3'"
+        help = "Comeplement motif pairs in the format: 'MOTIF_TYPE1_POS1_TYPE2_POS2', e.g. 'ACGT_a_0_m_3' or 'CCWGG_4mC_0_5mC_3'. The partner can be left out ('MOTIF_TYPE_POS', e.g. 'GATC_a_1') when the reverse complement defines it. Gaps can be written as 'N{7}', 'N(7)' or '(N7)', e.g. 'CCAN(7)GTGG_a_2'. Motifs can be at most 64 bases long, gaps included"
     )]
     pub motifs: Option<Vec<String>>,
 
@@ -38,6 +39,164 @@ pub struct Cli {
     )]
     pub min_cov: u32,
 
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_name = "CONTIG_IDS",
+        help = "Comma separated contig ids to treat as circular. Contigs with 'circular=true' in the fasta header are always circular"
+    )]
+    pub circular: Vec<String>,
+
+    #[arg(
+        value_enum,
+        long,
+        default_value = "all",
+        value_name = "ASSIGNMENT",
+        help = "How to report site pairs found by several motifs. 'all' writes a row for every motif, 'most-specific' and 'first' only for the most specific or first listed motif"
+    )]
+    pub site_assignment: SiteAssignment,
+
+    #[arg(
+        long,
+        default_value = "0",
+        value_name = "K",
+        help = "Number of flanking reference bases to report on each side of every motif pair row. Also writes the hemimethylation rate per flanking sequence and per base at position -1/+1"
+    )]
+    pub flank: usize,
+
+    #[arg(
+        value_enum,
+        long,
+        default_value = "bed",
+        value_name = "CONVENTION",
+        help = "Coordinate convention of positions in the output: 'bed' for 0-based, half-open intervals or 'one-based' for 1-based, closed intervals as in GFF and VCF. Motif occurrences crossing the origin of a circular contig end before they start"
+    )]
+    pub coordinates: Coordinates,
+
+    #[arg(
+        value_enum,
+        long,
+        default_value = "fisher",
+        value_name = "TEST",
+        help = "Exact test on the table of modified and unmodified reads of the two strands. 'fisher' conditions on the table margins, 'barnard' is unconditional and more powerful, but sums over all (n_1 + 1) * (n_2 + 1) tables of the two strand coverages for 165 values of the nuisance parameter per row, e.g. about 6 ms at 100 reads per strand"
+    )]
+    pub exact_test: ExactTest,
+
+    #[arg(
+        value_enum,
+        long,
+        default_value = "genome",
+        value_name = "SCOPE",
+        help = "Family of tests for the Bonferroni and Benjamini-Hochberg adjusted p-values of motif pair rows: all rows of a 'motif', all rows of a 'contig' or all rows in the 'genome'"
+    )]
+    pub correction_scope: CorrectionScope,
+
+    #[arg(
+        long,
+        help = "Also write Storey q-values, estimating the proportion of true null hypotheses from the p-values above 0.5"
+    )]
+    pub storey: bool,
+
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_name = "OFFSET_PAIRS",
+        help = "Comma separated pairs of a motif base and a partner at a fixed offset in the format: 'MOTIF_TYPE_POS_OFFSET_STRAND_TYPE', e.g. 'ACGT_a_0_+2_-_m'. The offset is counted along the motif and the strand is relative to the motif occurrence. The partner does not have to be part of the reverse complement motif. The motif can be at most 64 bases long"
+    )]
+    pub offset_pairs: Vec<String>,
+
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_name = "SITE_SETS",
+        help = "Comma separated motifs of at most 64 bases with several modified positions in the format: 'MOTIF_TYPE+POS_TYPE-POS...', e.g. 'GAAGN{6}CTTC_a+1_a+2_a-11_a-12'. '+' and '-' give the strand, positions are on the forward motif. Counts are written per occurrence together with its methylation pattern"
+    )]
+    pub site_sets: Vec<String>,
+
+    #[arg(
+        long,
+        default_value = "0.5",
+        help = "Minimum fraction of modified reads for a position to count as methylated in site set patterns and flanking context summaries, and methylation level of the posterior hemimethylation probability of motif pairs"
+    )]
+    pub methylation_threshold: f64,
+
+    #[arg(
+        value_enum,
+        long,
+        default_value = "fraction",
+        value_name = "METHOD",
+        help = "How sites are called for the methylation state of motif pairs. 'fraction' compares the fraction of modified reads with --unmethylated-fraction and --methylated-fraction, 'posterior' requires the beta-binomial posterior probability of the site being at least --methylation-threshold methylated, or not, to reach --state-probability. Hemimethylated pairs are labelled by site, 'hemimethylated_1' for the motif base and 'hemimethylated_2' for its partner, with the strand of the methylated site in the hemimethylated_strand column"
+    )]
+    pub state_method: StateMethod,
+
+    #[arg(
+        long,
+        default_value = "0.2",
+        value_name = "FRACTION",
+        help = "Highest fraction of modified reads of an unmethylated site in methylation states"
+    )]
+    pub unmethylated_fraction: f64,
+
+    #[arg(
+        long,
+        default_value = "0.8",
+        value_name = "FRACTION",
+        help = "Lowest fraction of modified reads of a methylated site in methylation states"
+    )]
+    pub methylated_fraction: f64,
+
+    #[arg(
+        long,
+        default_value = "0.95",
+        value_name = "PROBABILITY",
+        help = "Posterior probability needed to call a site methylated or unmethylated with '--state-method posterior'"
+    )]
+    pub state_probability: f64,
+
+    #[arg(
+        long,
+        default_value = "0",
+        value_name = "N",
+        help = "Number of bootstrap replicates resampling the site pairs of each motif for percentile intervals in the motif summaries, with the number of replicates where each statistic is defined. 0 disables the bootstrap"
+    )]
+    pub bootstrap: usize,
+
+    #[arg(
+        long,
+        help = "Also resample the reads of every bootstrapped site from a binomial with its observed fraction of modified reads"
+    )]
+    pub bootstrap_reads: bool,
+
+    #[arg(
+        long,
+        default_value = "1",
+        value_name = "SEED",
+        help = "Seed of the bootstrap, the same seed gives the same intervals"
+    )]
+    pub seed: u64,
+
+    #[arg(
+        long,
+        value_name = "PILEUP",
+        help = "Pileup file of a second condition to compare the motif pairs with. Writes site pairs covered in both pileups to differential_sites.tsv, ranked by a test of a change in the strand difference, and a test per contig and motif to differential_motifs.tsv"
+    )]
+    pub compare_pileup: Option<String>,
+
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_name = "MOTIFS",
+        help = "Comma separated motifs of at most 64 bases to sweep, e.g. 'CCWGG'. Writes the methylation at every motif position on both strands for each mod type in the pileup and marks the strongest position per strand, to confirm mod positions before pairing"
+    )]
+    pub sweep: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "ORDER",
+        help = "Write observed and expected occurrences of each motif per contig to motif_representation.tsv, using a Markov background of this order (capped at the motif length minus two), at most 9. Does not use the pileup"
+    )]
+    pub markov_order: Option<usize>,
+
     #[arg(long, short, default_value = "5", help = "Number of threads to use")]
     pub threads: u32,
 
@@ -59,8 +218,50 @@ pub struct Cli {
 }
 
 #[derive(ValueEnum, Clone, Debug)]
+#[allow(non_camel_case_types)]
 pub enum LogLevel {
     verbose,
     normal,
     silent,
 }
+
+#[derive(ValueEnum, Clone, Debug, PartialEq, Eq)]
+pub enum SiteAssignment {
+    All,
+    MostSpecific,
+    First,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Coordinates {
+    Bed,
+    OneBased,
+}
+
+impl From<Coordinates> for CoordinateSystem {
+    fn from(coordinates: Coordinates) -> Self {
+        match coordinates {
+            Coordinates::Bed => CoordinateSystem::Bed,
+            Coordinates::OneBased => CoordinateSystem::OneBased,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Debug, PartialEq, Eq)]
+pub enum ExactTest {
+    Fisher,
+    Barnard,
+}
+
+#[derive(ValueEnum, Clone, Debug, PartialEq, Eq)]
+pub enum CorrectionScope {
+    Motif,
+    Contig,
+    Genome,
+}
+
+#[derive(ValueEnum, Clone, Debug, PartialEq, Eq)]
+pub enum StateMethod {
+    Fraction,
+    Posterior,
+}
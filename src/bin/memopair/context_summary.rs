@@ -0,0 +1,144 @@
+use ahash::{HashMap, HashMapExt};
+use anyhow::Result;
+use std::fs::File;
+use utils::pileup::PileupRecord;
+
+/// Methylation state of a site pair, see [`ContextSummary::add`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct ContextCounts {
+    n_sites: u32,
+    n_methylated_1_only: u32,
+    n_methylated_2_only: u32,
+}
+
+impl ContextCounts {
+    fn hemimethylation_rate(&self) -> f64 {
+        (self.n_methylated_1_only + self.n_methylated_2_only) as f64 / self.n_sites as f64
+    }
+}
+
+/// Hemimethylation of the site pairs of each motif split by their flanking sequence.
+///
+/// Sites are grouped by the base right before and after the motif occurrence as well as
+/// by the full upstream and downstream flanks, each read on the strand of the occurrence.
+#[derive(Debug)]
+pub struct ContextSummary {
+    methylation_threshold: f64,
+    contexts: HashMap<(usize, &'static str, String), ContextCounts>,
+}
+
+impl ContextSummary {
+    pub fn new(methylation_threshold: f64) -> Self {
+        Self {
+            methylation_threshold,
+            contexts: HashMap::new(),
+        }
+    }
+
+    /// Counts a site pair as hemimethylated if exactly one of its sites reaches the
+    /// methylation threshold.
+    pub fn add(
+        &mut self,
+        motif_idx: usize,
+        upstream: &str,
+        downstream: &str,
+        record_1: &PileupRecord,
+        record_2: &PileupRecord,
+    ) {
+        let methylated_1 =
+            record_1.n_mod as f64 / record_1.n_valid_cov as f64 >= self.methylation_threshold;
+        let methylated_2 =
+            record_2.n_mod as f64 / record_2.n_valid_cov as f64 >= self.methylation_threshold;
+        let mut contexts = vec![("upstream", upstream), ("downstream", downstream)];
+        if let Some(i) = upstream.len().checked_sub(1) {
+            contexts.push(("-1", &upstream[i..]));
+        }
+        if !downstream.is_empty() {
+            contexts.push(("+1", &downstream[..1]));
+        }
+        for (context_type, context) in contexts {
+            let counts = self
+                .contexts
+                .entry((motif_idx, context_type, context.to_string()))
+                .or_default();
+            counts.n_sites += 1;
+            if methylated_1 && !methylated_2 {
+                counts.n_methylated_1_only += 1;
+            } else if methylated_2 && !methylated_1 {
+                counts.n_methylated_2_only += 1;
+            }
+        }
+    }
+
+    pub fn write(
+        &self,
+        out_path: &str,
+        reference: &str,
+        motif_names: &[String],
+    ) -> Result<(), anyhow::Error> {
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(b'\t')
+            .from_path(out_path)?;
+        write_header(&mut writer)?;
+
+        let mut contexts: Vec<(&(usize, &str, String), &ContextCounts)> =
+            self.contexts.iter().collect();
+        contexts.sort_by(|a, b| a.0.cmp(b.0));
+        for ((motif_idx, context_type, context), counts) in contexts {
+            writer.write_record(&[
+                reference.to_string(),
+                motif_names[*motif_idx].clone(),
+                context_type.to_string(),
+                context.clone(),
+                counts.n_sites.to_string(),
+                counts.n_methylated_1_only.to_string(),
+                counts.n_methylated_2_only.to_string(),
+                counts.hemimethylation_rate().to_string(),
+            ])?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+fn write_header(writer: &mut csv::Writer<File>) -> Result<(), anyhow::Error> {
+    writer.write_record([
+        "contig_id",
+        "motif",
+        "context_type",
+        "context",
+        "n_sites",
+        "n_methylated_1_only",
+        "n_methylated_2_only",
+        "hemimethylation_rate",
+    ])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::record;
+
+    #[test]
+    fn test_context_summary() {
+        let mut summary = ContextSummary::new(0.5);
+        summary.add(0, "AAA", "TC", &record(9), &record(1));
+        summary.add(0, "GCA", "TG", &record(2), &record(8));
+        summary.add(0, "GCA", "CG", &record(9), &record(9));
+
+        let counts = summary.contexts[&(0, "-1", "A".to_string())];
+        assert_eq!(counts.n_sites, 3);
+        assert_eq!(counts.n_methylated_1_only, 1);
+        assert_eq!(counts.n_methylated_2_only, 1);
+        assert!((counts.hemimethylation_rate() - 2.0 / 3.0).abs() < 1e-9);
+        assert_eq!(summary.contexts[&(0, "+1", "T".to_string())].n_sites, 2);
+        assert_eq!(summary.contexts[&(0, "upstream", "GCA".to_string())].n_sites, 2);
+        assert_eq!(summary.contexts[&(0, "downstream", "CG".to_string())].n_methylated_1_only, 0);
+
+        // Flanks cut off at a contig end
+        summary.add(1, "", "C", &record(9), &record(1));
+        assert!(!summary.contexts.contains_key(&(1, "-1", String::new())));
+        assert_eq!(summary.contexts[&(1, "upstream", String::new())].n_sites, 1);
+    }
+}
@@ -1,6 +1,7 @@
 use crate::pileup::PileupChunk;
 use crate::sequence::Contig;
 use ahash::{HashMap, HashMapExt};
+use utils::scanner::{MotifScanner, MotifSites};
 
 pub struct GenomeWorkSpaceBuilder {
     pub contigs: HashMap<String, Contig>,
@@ -36,3 +37,13 @@ impl GenomeWorkSpaceBuilder {
 pub struct GenomeWorkspace {
     pub contigs: HashMap<String, Contig>,
 }
+
+impl GenomeWorkspace {
+    /// Scans every contig once for all motifs of `scanner`, returning the sites per contig.
+    pub fn find_motif_sites(&self, scanner: &MotifScanner) -> HashMap<String, Vec<MotifSites>> {
+        self.contigs
+            .iter()
+            .map(|(reference, contig)| (reference.clone(), contig.scan(scanner)))
+            .collect()
+    }
+}
@@ -0,0 +1,490 @@
+use crate::{
+    cli,
+    methylated_motif_pair::{assigned_sites, PairSpec},
+    sequence::Contig,
+};
+use anyhow::{bail, Result};
+use log::{info, warn};
+use ahash::{HashMap, HashMapExt};
+use std::fs::File;
+use utils::{
+    coordinate::{CoordinateSystem, Position},
+    pileup::{PileupChunk, PileupChunkReader, PileupRecord},
+    scanner::MotifScanner,
+    stats,
+    strand::Strand,
+};
+
+/// A site pair covered in both conditions, with its table `[n_mod_1, n_nomod_1, n_mod_2,
+/// n_nomod_2]` in condition A (`PILEUP`) and B (`--compare-pileup`).
+#[derive(Debug, Clone, PartialEq)]
+struct DifferentialSite {
+    reference: String,
+    motif_idx: usize,
+    site_1: (Position, Strand),
+    site_2: (Position, Strand),
+    table_a: [u32; 4],
+    table_b: [u32; 4],
+}
+
+/// Test of a change of the strand difference of a site pair between the conditions.
+///
+/// The interaction of a logistic model of methylation with strand and condition is the
+/// difference of the corrected log odds ratios of site 1 vs. site 2, B minus A, with a
+/// Wald test. The Breslow-Day test asks the same of the two tables without the correction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct InteractionTest {
+    log_odds_ratio_a: f64,
+    log_odds_ratio_b: f64,
+    interaction: f64,
+    interaction_se: f64,
+    p_value: f64,
+    breslow_day: f64,
+    breslow_day_p_value: f64,
+}
+
+impl InteractionTest {
+    fn new(table_a: [u32; 4], table_b: [u32; 4]) -> Self {
+        let (log_odds_ratio_a, var_a) = stats::haldane_anscombe_log_odds_ratio(
+            table_a[0], table_a[1], table_a[2], table_a[3],
+        );
+        let (log_odds_ratio_b, var_b) = stats::haldane_anscombe_log_odds_ratio(
+            table_b[0], table_b[1], table_b[2], table_b[3],
+        );
+        let interaction = log_odds_ratio_b - log_odds_ratio_a;
+        let interaction_se = (var_a + var_b).sqrt();
+        let tables = [table_a, table_b];
+        let mantel_haenszel = stats::MantelHaenszel::new(&tables);
+        let (breslow_day, df) = stats::breslow_day(&tables, mantel_haenszel.odds_ratio);
+        Self {
+            log_odds_ratio_a,
+            log_odds_ratio_b,
+            interaction,
+            interaction_se,
+            p_value: stats::chi_squared_sf((interaction / interaction_se).powi(2), 1),
+            breslow_day,
+            breslow_day_p_value: stats::chi_squared_sf(breslow_day, df),
+        }
+    }
+}
+
+/// Fraction of modified reads of site 1 minus site 2.
+fn strand_difference(table: &[u32; 4]) -> f64 {
+    table[0] as f64 / (table[0] + table[1]) as f64 - table[2] as f64 / (table[2] + table[3]) as f64
+}
+
+/// Reads the next chunk of a pileup, or `None` once the whole file is read.
+fn next_chunk(reader: &mut PileupChunkReader<File>) -> Option<PileupChunk> {
+    loop {
+        match reader.next_chunk() {
+            Some(chunk) => return Some(chunk),
+            None if reader.eof_reached => return None,
+            None => continue,
+        }
+    }
+}
+
+/// Site pairs of a contig covered in both conditions.
+fn contig_sites(
+    contig: &Contig,
+    chunk_a: PileupChunk,
+    chunk_b: PileupChunk,
+    pairs: &[PairSpec],
+    scanner: &MotifScanner,
+    assignment: &cli::SiteAssignment,
+) -> Vec<DifferentialSite> {
+    let mut contig_a = contig.clone();
+    contig_a.add_records(chunk_a);
+    let mut contig_b = contig.clone();
+    contig_b.add_records(chunk_b);
+    let motif_sites = contig_a.scan(scanner);
+
+    let mut sites = Vec::new();
+    for (site, _) in assigned_sites(&contig_a, pairs, &motif_sites, assignment) {
+        let pair = &pairs[site.motif_idx].pair;
+        let key_1 = (site.site_1.0, site.site_1.1, pair.motif.mod_type);
+        let key_2 = (site.site_2.0, site.site_2.1, pair.partner_mod_type);
+        let records = (
+            contig_a.records.get(&key_1),
+            contig_a.records.get(&key_2),
+            contig_b.records.get(&key_1),
+            contig_b.records.get(&key_2),
+        );
+        if let (Some(a_1), Some(a_2), Some(b_1), Some(b_2)) = records {
+            let table = |r_1: &PileupRecord, r_2: &PileupRecord| {
+                [r_1.n_mod, r_1.n_valid_cov - r_1.n_mod, r_2.n_mod, r_2.n_valid_cov - r_2.n_mod]
+            };
+            sites.push(DifferentialSite {
+                reference: contig.reference.clone(),
+                motif_idx: site.motif_idx,
+                site_1: site.site_1,
+                site_2: site.site_2,
+                table_a: table(a_1, a_2),
+                table_b: table(b_1, b_2),
+            });
+        }
+    }
+    sites
+}
+
+/// Compares the motif pairs of `PILEUP` (condition A) with those of `--compare-pileup`
+/// (condition B), writing the site pairs ranked by the interaction p-value to
+/// `differential_sites.tsv` and a test per contig and motif to `differential_motifs.tsv`.
+///
+/// Contigs are read from both pileups as they come and matched by id, so pileups
+/// sorted in the same contig order only keep a few contigs in memory.
+pub fn differential_hemimethylation(
+    reference: &HashMap<String, Contig>,
+    pairs: &[PairSpec],
+    scanner: &MotifScanner,
+    compare_pileup: &str,
+    args: &cli::Cli,
+) -> Result<(), anyhow::Error> {
+    let open = |path: &str| {
+        File::open(path)
+            .map_err(|e| anyhow::anyhow!("Could not open pileup file: {} ({})", path, e))
+    };
+    let mut reader_a = PileupChunkReader::new(open(&args.pileup)?, args.min_cov);
+    let mut reader_b = PileupChunkReader::new(open(compare_pileup)?, args.min_cov);
+    info!("Comparing pileup {} with {}", args.pileup, compare_pileup);
+
+    let mut pending_a: HashMap<String, PileupChunk> = HashMap::new();
+    let mut pending_b: HashMap<String, PileupChunk> = HashMap::new();
+    let mut sites = Vec::new();
+    let mut contig_ids = Vec::new();
+    loop {
+        let (chunk_a, chunk_b) = (next_chunk(&mut reader_a), next_chunk(&mut reader_b));
+        if chunk_a.is_none() && chunk_b.is_none() {
+            break;
+        }
+        let mut matched = Vec::new();
+        if let Some(chunk) = chunk_a {
+            match pending_b.remove(&chunk.reference) {
+                Some(other) => matched.push((chunk, other)),
+                None => {
+                    pending_a.insert(chunk.reference.clone(), chunk);
+                }
+            }
+        }
+        if let Some(chunk) = chunk_b {
+            match pending_a.remove(&chunk.reference) {
+                Some(other) => matched.push((other, chunk)),
+                None => {
+                    pending_b.insert(chunk.reference.clone(), chunk);
+                }
+            }
+        }
+        for (chunk_a, chunk_b) in matched {
+            let contig = match reference.get(&chunk_a.reference) {
+                Some(contig) => contig,
+                None => bail!("Contig not found in reference: {}", chunk_a.reference),
+            };
+            info!("Comparing contig: {}", contig.reference);
+            contig_ids.push(contig.reference.clone());
+            sites.extend(contig_sites(contig, chunk_a, chunk_b, pairs, scanner, &args.site_assignment));
+        }
+    }
+    for contig_id in pending_a.keys().chain(pending_b.keys()) {
+        warn!("Contig {} is only in one of the compared pileups", contig_id);
+    }
+
+    let motif_names: Vec<String> = pairs.iter().map(|spec| spec.name.clone()).collect();
+    write_sites(
+        &format!("{}/differential_sites.tsv", args.out),
+        &sites,
+        &motif_names,
+        args,
+    )?;
+    write_motifs(
+        &format!("{}/differential_motifs.tsv", args.out),
+        &contig_ids,
+        &sites,
+        &motif_names,
+    )?;
+    Ok(())
+}
+
+/// Tests of all site pairs, ordered from the most significant change in strand difference
+/// to the least, breaking ties by the size of the interaction.
+fn ranked_tests(sites: &[DifferentialSite]) -> Vec<(usize, InteractionTest)> {
+    let mut tests: Vec<(usize, InteractionTest)> = sites
+        .iter()
+        .enumerate()
+        .map(|(i, site)| (i, InteractionTest::new(site.table_a, site.table_b)))
+        .collect();
+    tests.sort_by(|(_, a), (_, b)| {
+        a.p_value
+            .total_cmp(&b.p_value)
+            .then(b.interaction.abs().total_cmp(&a.interaction.abs()))
+    });
+    tests
+}
+
+fn write_sites(
+    out_path: &str,
+    sites: &[DifferentialSite],
+    motif_names: &[String],
+    args: &cli::Cli,
+) -> Result<(), anyhow::Error> {
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(b'\t')
+        .from_path(out_path)?;
+    writer.write_record([
+        "rank",
+        "contig_id",
+        "motif",
+        "position_1",
+        "strand_1",
+        "position_2",
+        "strand_2",
+        "n_mod_1_a",
+        "n_nomod_1_a",
+        "n_mod_2_a",
+        "n_nomod_2_a",
+        "n_mod_1_b",
+        "n_nomod_1_b",
+        "n_mod_2_b",
+        "n_nomod_2_b",
+        "strand_difference_a",
+        "strand_difference_b",
+        "difference_change",
+        "log_odds_ratio_a",
+        "log_odds_ratio_b",
+        "interaction",
+        "interaction_se",
+        "p_value",
+        "p_value_bh",
+        "breslow_day",
+        "breslow_day_p_value",
+    ])?;
+
+    let coordinates = CoordinateSystem::from(args.coordinates);
+    let tests = ranked_tests(sites);
+    let p_values: Vec<f64> = tests.iter().map(|(_, test)| test.p_value).collect();
+    let adjusted = stats::benjamini_hochberg(&p_values);
+    for (rank, ((site_idx, test), p_value_bh)) in tests.iter().zip(adjusted.iter()).enumerate() {
+        let site = &sites[*site_idx];
+        let (difference_a, difference_b) =
+            (strand_difference(&site.table_a), strand_difference(&site.table_b));
+        let mut row = vec![
+            (rank + 1).to_string(),
+            site.reference.clone(),
+            motif_names[site.motif_idx].clone(),
+            coordinates.position(site.site_1.0).to_string(),
+            site.site_1.1.to_string(),
+            coordinates.position(site.site_2.0).to_string(),
+            site.site_2.1.to_string(),
+        ];
+        row.extend(site.table_a.iter().chain(site.table_b.iter()).map(|n| n.to_string()));
+        row.extend(
+            [
+                difference_a,
+                difference_b,
+                difference_b - difference_a,
+                test.log_odds_ratio_a,
+                test.log_odds_ratio_b,
+                test.interaction,
+                test.interaction_se,
+                test.p_value,
+                *p_value_bh,
+                test.breslow_day,
+                test.breslow_day_p_value,
+            ]
+            .iter()
+            .map(|value| value.to_string()),
+        );
+        writer.write_record(&row)?;
+    }
+    writer.flush()?;
+    info!("Compared {} site pairs covered in both pileups", sites.len());
+    Ok(())
+}
+
+/// Change of the strand difference of a motif in a contig, comparing the Mantel-Haenszel
+/// odds ratios of its site pairs in the two conditions.
+///
+/// If either odds ratio is 0, infinite or undefined, e.g. for a motif fully methylated on
+/// both strands, both are Haldane-Anscombe corrected so that they can still be compared.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct MotifComparison {
+    n_sites: usize,
+    mean_difference_a: f64,
+    mean_difference_b: f64,
+    odds_ratio_a: f64,
+    odds_ratio_b: f64,
+    corrected: bool,
+    z_score: f64,
+    p_value: f64,
+}
+
+impl MotifComparison {
+    fn new(sites: &[&DifferentialSite]) -> Self {
+        let n_sites = sites.len();
+        let mean = |f: fn(&DifferentialSite) -> f64| {
+            sites.iter().map(|site| f(site)).sum::<f64>() / n_sites as f64
+        };
+        let tables_a: Vec<[u32; 4]> = sites.iter().map(|site| site.table_a).collect();
+        let tables_b: Vec<[u32; 4]> = sites.iter().map(|site| site.table_b).collect();
+        let (mut mantel_haenszel_a, mut mantel_haenszel_b) = (
+            stats::MantelHaenszel::new(&tables_a),
+            stats::MantelHaenszel::new(&tables_b),
+        );
+        let corrected =
+            n_sites > 0 && !(mantel_haenszel_a.is_estimable() && mantel_haenszel_b.is_estimable());
+        if corrected {
+            mantel_haenszel_a = stats::MantelHaenszel::haldane_anscombe(&tables_a);
+            mantel_haenszel_b = stats::MantelHaenszel::haldane_anscombe(&tables_b);
+        }
+        let z_score = (mantel_haenszel_b.odds_ratio.ln() - mantel_haenszel_a.odds_ratio.ln())
+            / (mantel_haenszel_a.log_odds_ratio_var + mantel_haenszel_b.log_odds_ratio_var).sqrt();
+        Self {
+            n_sites,
+            mean_difference_a: mean(|site| strand_difference(&site.table_a)),
+            mean_difference_b: mean(|site| strand_difference(&site.table_b)),
+            odds_ratio_a: mantel_haenszel_a.odds_ratio,
+            odds_ratio_b: mantel_haenszel_b.odds_ratio,
+            corrected,
+            z_score,
+            p_value: stats::chi_squared_sf(z_score * z_score, 1),
+        }
+    }
+}
+
+/// Writes one row per compared contig and motif, including motifs without site pairs.
+///
+/// Sites are grouped by contig and motif once, as there are many more sites than motifs.
+fn write_motifs(
+    out_path: &str,
+    contig_ids: &[String],
+    sites: &[DifferentialSite],
+    motif_names: &[String],
+) -> Result<(), anyhow::Error> {
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(b'\t')
+        .from_path(out_path)?;
+    writer.write_record([
+        "contig_id",
+        "motif",
+        "n_sites",
+        "mean_strand_difference_a",
+        "mean_strand_difference_b",
+        "mh_odds_ratio_a",
+        "mh_odds_ratio_b",
+        "haldane_anscombe_corrected",
+        "z_score",
+        "p_value",
+    ])?;
+    let mut grouped: HashMap<(&str, usize), Vec<&DifferentialSite>> = HashMap::new();
+    for site in sites.iter() {
+        grouped
+            .entry((site.reference.as_str(), site.motif_idx))
+            .or_default()
+            .push(site);
+    }
+    for contig_id in contig_ids {
+        for (motif_idx, motif_name) in motif_names.iter().enumerate() {
+            let motif_sites = grouped
+                .get(&(contig_id.as_str(), motif_idx))
+                .map_or(&[][..], |sites| sites.as_slice());
+            let comparison = MotifComparison::new(motif_sites);
+            let mut row = vec![
+                contig_id.clone(),
+                motif_name.clone(),
+                comparison.n_sites.to_string(),
+            ];
+            row.extend(
+                [
+                    comparison.mean_difference_a,
+                    comparison.mean_difference_b,
+                    comparison.odds_ratio_a,
+                    comparison.odds_ratio_b,
+                ]
+                .iter()
+                .map(|value| value.to_string()),
+            );
+            row.push(comparison.corrected.to_string());
+            row.extend(
+                [
+                    comparison.z_score,
+                    comparison.p_value,
+                ]
+                .iter()
+                .map(|value| value.to_string()),
+            );
+            writer.write_record(&row)?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn site(motif_idx: usize, table_a: [u32; 4], table_b: [u32; 4]) -> DifferentialSite {
+        DifferentialSite {
+            reference: "contig_1".to_string(),
+            motif_idx,
+            site_1: (Position::new(10), Strand::Positive),
+            site_2: (Position::new(11), Strand::Negative),
+            table_a,
+            table_b,
+        }
+    }
+
+    #[test]
+    fn test_interaction_test() {
+        // Same tables in both conditions
+        let test = InteractionTest::new([18, 2, 3, 17], [18, 2, 3, 17]);
+        assert!(test.interaction.abs() < 1e-12);
+        assert!((test.p_value - 1.0).abs() < 1e-9);
+        assert!(test.breslow_day.abs() < 1e-9);
+
+        // Hemimethylated in A, fully methylated in B
+        let test = InteractionTest::new([18, 2, 3, 17], [18, 2, 17, 3]);
+        assert!(test.log_odds_ratio_a > 0.0);
+        assert!(test.interaction < 0.0);
+        assert!((test.interaction - (test.log_odds_ratio_b - test.log_odds_ratio_a)).abs() < 1e-12);
+        assert!((test.p_value - 0.0106).abs() < 1e-3);
+        assert!(test.breslow_day_p_value < 0.01);
+        assert!((strand_difference(&[18, 2, 3, 17]) - 0.75).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_ranked_tests_and_motif_comparison() {
+        let sites = vec![
+            site(0, [10, 10, 10, 10], [10, 10, 10, 10]),
+            site(0, [18, 2, 3, 17], [18, 2, 17, 3]),
+            site(1, [18, 2, 3, 17], [12, 8, 9, 11]),
+        ];
+        let ranked: Vec<usize> = ranked_tests(&sites).iter().map(|(i, _)| *i).collect();
+        // The smaller change of site 2 is measured more precisely in condition B
+        assert_eq!(ranked, vec![2, 1, 0]);
+
+        let comparison = MotifComparison::new(&[&sites[1], &sites[2]]);
+        assert_eq!(comparison.n_sites, 2);
+        assert!((comparison.mean_difference_a - 0.75).abs() < 1e-12);
+        assert!((comparison.mean_difference_b - 0.1).abs() < 1e-12);
+        assert!(comparison.odds_ratio_a > comparison.odds_ratio_b);
+        assert!(!comparison.corrected);
+        assert!(comparison.z_score < 0.0);
+        assert!(comparison.p_value < 0.05);
+
+        // Fully methylated in A, where the plain odds ratio is undefined
+        let fully_methylated = [
+            site(0, [20, 0, 20, 0], [18, 2, 3, 17]),
+            site(0, [15, 0, 15, 0], [16, 4, 2, 18]),
+        ];
+        let comparison = MotifComparison::new(&[&fully_methylated[0], &fully_methylated[1]]);
+        assert!(comparison.corrected);
+        assert!((comparison.odds_ratio_a - 1.0).abs() < 1e-12);
+        assert!(comparison.odds_ratio_b > 1.0);
+        assert!(comparison.z_score > 0.0 && comparison.p_value < 0.05);
+
+        let empty = MotifComparison::new(&[]);
+        assert_eq!(empty.n_sites, 0);
+        assert!(!empty.corrected);
+        assert!(empty.p_value.is_nan());
+    }
+}
@@ -0,0 +1,145 @@
+use crate::run_statistics::{ContigRows, PairRow};
+use anyhow::Result;
+use log::debug;
+use utils::duplex::DuplexMixture;
+
+/// Column names of the duplex states, in the order of `utils::duplex::DUPLEX_STATES`.
+const STATE_NAMES: [&str; 4] = [
+    "fully_methylated",
+    "hemimethylated_1",
+    "hemimethylated_2",
+    "unmethylated",
+];
+
+fn table(row: &PairRow) -> [u32; 4] {
+    [
+        row.site_1.n_mod,
+        row.site_1.n_valid_cov - row.site_1.n_mod,
+        row.site_2.n_mod,
+        row.site_2.n_valid_cov - row.site_2.n_mod,
+    ]
+}
+
+/// Values of a row of the duplex state table, NaN for motifs without site pairs.
+fn duplex_values(tables: &[[u32; 4]]) -> Vec<f64> {
+    let mixture = match DuplexMixture::fit(tables) {
+        Some(mixture) => mixture,
+        None => return vec![f64::NAN; 3 * STATE_NAMES.len() + 5],
+    };
+    let mut values = Vec::new();
+    for (state, fraction) in mixture.fractions.iter().enumerate() {
+        let interval = mixture.profile_interval(tables, state);
+        values.extend([*fraction, interval.low, interval.high]);
+    }
+    values.extend(mixture.unmethylated_rate);
+    values.extend(mixture.methylated_rate);
+    values.push(mixture.log_likelihood);
+    values
+}
+
+/// Writes the estimated fractions of duplexes per state with profile likelihood
+/// intervals for each contig and motif, see [`DuplexMixture`] for the assumptions.
+///
+/// `hemimethylated_1` has only site 1 (the motif base) methylated, `hemimethylated_2`
+/// only its partner. The modified read rates are fitted per site, so pairs of different
+/// mod types, such as a 4mC partner of a 6mA site, do not share them.
+///
+/// The profile likelihood intervals refit the model about 80 times per state, which
+/// dominates the run time for motifs with many site pairs.
+pub fn write_duplex_states(
+    out_path: &str,
+    contigs: &[ContigRows],
+    motif_names: &[String],
+) -> Result<(), anyhow::Error> {
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(b'\t')
+        .from_path(out_path)?;
+    let mut header = vec!["contig_id".to_string(), "motif".to_string(), "n_sites".to_string()];
+    for name in STATE_NAMES {
+        header.extend([name.to_string(), format!("{}_low", name), format!("{}_high", name)]);
+    }
+    header.extend(
+        [
+            "unmethylated_rate_1",
+            "unmethylated_rate_2",
+            "methylated_rate_1",
+            "methylated_rate_2",
+            "log_likelihood",
+        ]
+        .map(String::from),
+    );
+    writer.write_record(&header)?;
+
+    for contig in contigs.iter() {
+        for (motif_idx, motif_name) in motif_names.iter().enumerate() {
+            let tables: Vec<[u32; 4]> = contig
+                .rows
+                .iter()
+                .filter(|row| row.motif_idx == motif_idx)
+                .map(table)
+                .collect();
+            debug!(
+                "Fitting duplex states of {} in {} to {} site pairs",
+                motif_name,
+                contig.reference,
+                tables.len()
+            );
+            let mut record = vec![
+                contig.reference.clone(),
+                motif_name.clone(),
+                tables.len().to_string(),
+            ];
+            record.extend(duplex_values(&tables).iter().map(|value| value.to_string()));
+            writer.write_record(&record)?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run_statistics::SiteCounts;
+    use utils::{modtype::ModType, strand::Strand};
+
+    #[test]
+    fn test_write_duplex_states() {
+        let site = |strand, n_mod| SiteCounts {
+            strand,
+            mod_type: ModType::SixMA,
+            n_mod,
+            n_valid_cov: 20,
+        };
+        let rows = (0..10)
+            .map(|i| PairRow {
+                motif_idx: 0,
+                p_value: 1.0,
+                site_1: site(Strand::Positive, 20),
+                site_2: site(Strand::Negative, if i < 5 { 20 } else { 0 }),
+            })
+            .collect();
+        let contigs = [ContigRows {
+            reference: "contig_1".to_string(),
+            rows,
+        }];
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("duplex_states.tsv");
+        let path = path.to_str().unwrap();
+        let motif_names = ["GATC_a_1".to_string(), "CCWGG_m_1".to_string()];
+        write_duplex_states(path, &contigs, &motif_names).unwrap();
+
+        let mut reader = csv::ReaderBuilder::new().delimiter(b'\t').from_path(path).unwrap();
+        let header = reader.headers().unwrap().clone();
+        assert_eq!(header.len(), 20);
+        assert_eq!(&header[6], "hemimethylated_1");
+        let records: Vec<csv::StringRecord> = reader.records().map(|r| r.unwrap()).collect();
+        assert_eq!(records.len(), 2);
+        let value = |record: &csv::StringRecord, i: usize| record[i].parse::<f64>().unwrap();
+        assert!((value(&records[0], 3) - 0.5).abs() < 1e-6);
+        assert!((value(&records[0], 6) - 0.5).abs() < 1e-6);
+        assert!(value(&records[0], 7) < 0.5 && value(&records[0], 8) > 0.5);
+        assert_eq!(&records[1][2], "0");
+        assert!(value(&records[1], 3).is_nan());
+    }
+}
@@ -0,0 +1,204 @@
+use crate::run_statistics::{ContigRows, RunColumns, SiteCounts};
+use ahash::{HashMap, HashMapExt};
+use anyhow::Result;
+use log::info;
+use utils::{
+    beta_binomial::{hemimethylation_probability, BetaPrior},
+    modtype::ModType,
+};
+
+/// Beta-binomial prior of the sites of one motif and mod type.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MotifPrior {
+    n_sites: usize,
+    prior: BetaPrior,
+}
+
+/// Fits a beta-binomial prior per motif and mod type to the counts of both sites of
+/// every row in the run.
+pub fn fit_priors(contigs: &[ContigRows]) -> HashMap<(usize, ModType), MotifPrior> {
+    let mut counts: HashMap<(usize, ModType), Vec<(u32, u32)>> = HashMap::new();
+    for row in contigs.iter().flat_map(|contig| contig.rows.iter()) {
+        for site in [&row.site_1, &row.site_2] {
+            counts
+                .entry((row.motif_idx, site.mod_type))
+                .or_default()
+                .push((site.n_mod, site.n_valid_cov));
+        }
+    }
+    counts
+        .into_iter()
+        .filter_map(|(key, counts)| {
+            BetaPrior::fit(&counts).map(|prior| {
+                (
+                    key,
+                    MotifPrior {
+                        n_sites: counts.len(),
+                        prior,
+                    },
+                )
+            })
+        })
+        .collect()
+}
+
+/// Posterior methylation of a site of a row of motif `motif_idx`, or `None` if no prior
+/// could be fitted because no site of the motif and mod type has any reads.
+pub fn site_posterior(
+    priors: &HashMap<(usize, ModType), MotifPrior>,
+    motif_idx: usize,
+    site: &SiteCounts,
+) -> Option<BetaPrior> {
+    priors
+        .get(&(motif_idx, site.mod_type))
+        .map(|prior| prior.prior.posterior(site.n_mod, site.n_valid_cov))
+}
+
+/// Shrunken methylation of both sites of every row and the posterior probability that
+/// exactly one of them is methylated at `methylation_threshold`, NaN without a prior.
+pub fn posterior_columns(
+    contigs: &[ContigRows],
+    priors: &HashMap<(usize, ModType), MotifPrior>,
+    methylation_threshold: f64,
+) -> RunColumns {
+    let values = contigs
+        .iter()
+        .map(|contig| {
+            contig
+                .rows
+                .iter()
+                .map(|row| {
+                    let posterior_1 = site_posterior(priors, row.motif_idx, &row.site_1);
+                    let posterior_2 = site_posterior(priors, row.motif_idx, &row.site_2);
+                    let mean = |posterior: Option<BetaPrior>| posterior.map_or(f64::NAN, |p| p.mean());
+                    let hemimethylation = match (posterior_1, posterior_2) {
+                        (Some(posterior_1), Some(posterior_2)) => hemimethylation_probability(
+                            &posterior_1,
+                            &posterior_2,
+                            methylation_threshold,
+                        ),
+                        _ => f64::NAN,
+                    };
+                    vec![
+                        mean(posterior_1).to_string(),
+                        mean(posterior_2).to_string(),
+                        hemimethylation.to_string(),
+                    ]
+                })
+                .collect()
+        })
+        .collect();
+    RunColumns {
+        names: vec![
+            "shrunken_methylation_1",
+            "shrunken_methylation_2",
+            "posterior_hemimethylation",
+        ],
+        values,
+    }
+}
+
+pub fn write_priors(
+    out_path: &str,
+    priors: &HashMap<(usize, ModType), MotifPrior>,
+    motif_names: &[String],
+) -> Result<(), anyhow::Error> {
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(b'\t')
+        .from_path(out_path)?;
+    writer.write_record([
+        "motif",
+        "mod_type",
+        "n_sites",
+        "alpha",
+        "beta",
+        "mean_methylation",
+        "overdispersion",
+    ])?;
+    let mut keys: Vec<&(usize, ModType)> = priors.keys().collect();
+    keys.sort_by_key(|(motif_idx, mod_type)| (*motif_idx, mod_type.to_string()));
+    for key in keys {
+        let MotifPrior { n_sites, prior } = priors[key];
+        info!(
+            "Beta-binomial prior of {} ({}): mean {:.3}, overdispersion {:.3}",
+            motif_names[key.0],
+            key.1.to_string(),
+            prior.mean(),
+            prior.overdispersion()
+        );
+        writer.write_record(&[
+            motif_names[key.0].clone(),
+            key.1.to_string().to_string(),
+            n_sites.to_string(),
+            prior.alpha.to_string(),
+            prior.beta.to_string(),
+            prior.mean().to_string(),
+            prior.overdispersion().to_string(),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run_statistics::PairRow;
+    use utils::strand::Strand;
+
+    fn counts(mod_type: ModType, n_mod: u32) -> SiteCounts {
+        SiteCounts {
+            strand: Strand::Positive,
+            mod_type,
+            n_mod,
+            n_valid_cov: 20,
+        }
+    }
+
+    #[test]
+    fn test_fit_priors_and_posteriors() {
+        // Motif 0 pairs a methylated 6mA site with an unmethylated 5mC site
+        let rows = (0..10)
+            .map(|_| PairRow {
+                motif_idx: 0,
+                p_value: 0.0,
+                site_1: counts(ModType::SixMA, 19),
+                site_2: counts(ModType::FiveMC, 1),
+            })
+            .collect();
+        let contigs = [ContigRows {
+            reference: "contig_1".to_string(),
+            rows,
+        }];
+        let priors = fit_priors(&contigs);
+        assert_eq!(priors.len(), 2);
+        assert_eq!(priors[&(0, ModType::SixMA)].n_sites, 10);
+        assert!(priors[&(0, ModType::SixMA)].prior.mean() > 0.9);
+        assert!(priors[&(0, ModType::FiveMC)].prior.mean() < 0.1);
+
+        let columns = posterior_columns(&contigs, &priors, 0.5);
+        assert_eq!(columns.values[0].len(), 10);
+        let row = &columns.values[0][0];
+        let hemimethylation: f64 = row[2].parse().unwrap();
+        assert!(hemimethylation > 0.99);
+
+        // Sites without reads give no prior and no posterior
+        let mut uncovered = counts(ModType::FourMC, 0);
+        uncovered.n_valid_cov = 0;
+        let contigs = [ContigRows {
+            reference: "contig_1".to_string(),
+            rows: vec![PairRow {
+                motif_idx: 1,
+                p_value: 1.0,
+                site_1: counts(ModType::SixMA, 19),
+                site_2: uncovered,
+            }],
+        }];
+        let priors = fit_priors(&contigs);
+        assert!(site_posterior(&priors, 1, &uncovered).is_none());
+        let row = &posterior_columns(&contigs, &priors, 0.5).values[0][0];
+        assert!(row[0].parse::<f64>().unwrap() > 0.5);
+        assert_eq!(row[1], "NaN");
+        assert_eq!(row[2], "NaN");
+    }
+}
@@ -1,9 +1,10 @@
 use crate::sequence::Contig;
-use std::collections::HashMap;
+use ahash::{HashMap, HashMapExt};
 use anyhow::Context;
 use anyhow::Result;
 use seq_io::fasta::{Reader, Record};
 use std::path::Path;
+use utils::packed_sequence::PackedSequence;
 
 pub fn read_fasta_file(file_path: &Path) -> Result<HashMap<String, Contig>> {
     let mut reader = Reader::from_path(file_path)
@@ -17,11 +18,43 @@ pub fn read_fasta_file(file_path: &Path) -> Result<HashMap<String, Contig>> {
             .id()
             .map(|id| id.to_string())
             .with_context(|| "Error getting fasta record id")?;
-        let sequence = String::from_utf8(record.owned_seq())
-            .with_context(|| "Error getting fasta record sequence")?;
+        let mut sequence = PackedSequence::with_capacity(record.seq().len());
+        for line in record.seq_lines() {
+            sequence.extend_from_bytes(line);
+        }
 
-        let contig: Contig = Contig::new(&id, &sequence);
+        let circular = record
+            .desc()
+            .and_then(|desc| desc.ok())
+            .is_some_and(is_circular_description);
+
+        let contig: Contig = Contig::from_packed(&id, sequence).with_circular(circular);
         records.insert(id, contig);
     }
     Ok(records)
 }
+
+/// Checks a FASTA header description for a `circular=true` tag.
+fn is_circular_description(description: &str) -> bool {
+    description.split_whitespace().any(|field| {
+        field
+            .split_once('=')
+            .is_some_and(|(key, value)| {
+                key.eq_ignore_ascii_case("circular") && value.eq_ignore_ascii_case("true")
+            })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_circular_description() {
+        assert!(is_circular_description("length=4641652 depth=1.00x circular=true"));
+        assert!(is_circular_description("circular=True"));
+        assert!(!is_circular_description("length=4641652 depth=1.00x"));
+        assert!(!is_circular_description("circular=false"));
+        assert!(!is_circular_description("circular"));
+    }
+}
@@ -1,15 +1,28 @@
 use clap::Parser;
 use env_logger::Env;
 use log::info;
-use utils::motif;
 use std::path::Path;
 use utils::pileup;
 
 mod methylated_motif_pair;
 mod cli;
+mod context_summary;
 mod data;
+mod differential;
+mod duplex_states;
+mod empirical_bayes;
 mod fasta_reader;
+mod methylation_state;
+mod position_sweep;
+mod motif_representation;
+mod motif_summary;
+mod multiple_testing;
+mod run_statistics;
 mod sequence;
+mod site_set_pattern;
+#[cfg(test)]
+mod test_utils;
+mod variant_summary;
 
 fn main() {
     let args = cli::Cli::parse();
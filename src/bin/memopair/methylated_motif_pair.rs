@@ -1,8 +1,14 @@
 use anyhow::{bail, Result};
-use log::{debug, info};
+use ahash::{HashMap, HashMapExt, HashSet};
+use log::{debug, info, warn};
 use utils::{
+    coordinate::{CoordinateSystem, Interval, Position},
+    markov,
     motif, 
     motif::MotifLike, 
+    scanner::{MotifScanner, MotifSites},
+    site_set::MotifSiteSet,
+    stats,
     strand::Strand,
     pileup
 };
@@ -13,24 +19,103 @@ use crate::{
     fasta_reader,
     sequence,
     data,
-    cli
+    cli,
+    differential,
+    context_summary,
+    motif_representation,
+    motif_summary,
+    position_sweep,
+    run_statistics,
+    site_set_pattern,
+    variant_summary
 };
 
 pub fn memopair(args: &cli::Cli) -> Result<(), anyhow::Error> {
     let global_timer = Instant::now();
     let motifs = match &args.motifs {
         Some(motifs) => parse_motif_pair_strings(motifs.clone())?,
-        None => bail!("No motifs provided"),
+        None => Vec::new(),
     };
+    let site_sets = args
+        .site_sets
+        .iter()
+        .map(|s| s.parse::<MotifSiteSet>())
+        .collect::<Result<Vec<MotifSiteSet>>>()?;
+    let offset_pairs = args
+        .offset_pairs
+        .iter()
+        .map(|s| s.parse::<motif::OffsetPair>())
+        .collect::<Result<Vec<motif::OffsetPair>>>()?;
+    let sweep_motifs = args
+        .sweep
+        .iter()
+        .map(|s| position_sweep::parse_sweep_motif(s))
+        .collect::<Result<Vec<_>>>()?;
+    if motifs.is_empty() && offset_pairs.is_empty() && site_sets.is_empty() && sweep_motifs.is_empty() {
+        bail!("No motifs provided");
+    }
+    if args.unmethylated_fraction >= args.methylated_fraction {
+        bail!(
+            "--unmethylated-fraction ({}) must be below --methylated-fraction ({})",
+            args.unmethylated_fraction,
+            args.methylated_fraction
+        );
+    }
+    if args.state_probability <= 0.5 || args.state_probability > 1.0 {
+        bail!("--state-probability must be above 0.5 and at most 1, got {}", args.state_probability);
+    }
+    if let Some(order) = args.markov_order {
+        if order > markov::MAX_ORDER {
+            bail!("--markov-order must be at most {}, got {}", markov::MAX_ORDER, order);
+        }
+    }
+    log_redundant_motifs(&motifs);
+    let pairs: Vec<PairSpec> = motifs
+        .iter()
+        .map(PairSpec::from)
+        .chain(offset_pairs.into_iter().map(PairSpec::from))
+        .collect();
+    let scanner = MotifScanner::new(pairs.iter().map(|spec| &spec.pair.motif))?;
+    let site_set_scanner =
+        MotifScanner::from_sequences(site_sets.iter().map(|set| set.sequence.as_slice()))?;
+    let sweep_scanner = MotifScanner::from_sequences(sweep_motifs.iter().map(|m| m.as_slice()))?;
     let reference_file = Path::new(&args.reference);
-    let reference = fasta_reader::read_fasta_file(reference_file)
+    let mut reference = fasta_reader::read_fasta_file(reference_file)
         .map_err(|e| anyhow::anyhow!("Error reading reference file: {}", e))?;
-    info!("Loaded {} reference records", reference.len());
+    for contig_id in args.circular.iter() {
+        match reference.get_mut(contig_id) {
+            Some(contig) => contig.circular = true,
+            None => bail!("Circular contig not found in reference: {}", contig_id),
+        }
+    }
+    info!(
+        "Loaded {} reference records ({} circular)",
+        reference.len(),
+        reference.values().filter(|contig| contig.circular).count()
+    );
+    if let Some(order) = args.markov_order {
+        let mut representation_motifs: Vec<&motif::Motif> = Vec::new();
+        for spec in pairs.iter() {
+            if representation_motifs
+                .iter()
+                .all(|m| m.sequence != spec.pair.motif.sequence)
+            {
+                representation_motifs.push(&spec.pair.motif);
+            }
+        }
+        motif_representation::write_motif_representation(
+            &reference,
+            &representation_motifs,
+            order,
+            &args.out,
+        )?;
+    }
 
     let pileup_file = File::open(&args.pileup)
         .map_err(|e| anyhow::anyhow!("Could not open pileup file: {} ({})", args.pileup, e))?;
     let mut pileup_reader = pileup::PileupChunkReader::new(pileup_file, args.min_cov);
 
+    let mut pair_rows = Vec::new();
     info!("Processing pileup file: {}", args.pileup);
     loop {
         info!("Processing a batch");
@@ -49,9 +134,35 @@ pub fn memopair(args: &cli::Cli) -> Result<(), anyhow::Error> {
                     builder.push_records(chunk);
                 }
                 let genome_work_space = builder.build();
+                let mut motif_sites = genome_work_space.find_motif_sites(&scanner);
+                let mut site_set_sites = genome_work_space.find_motif_sites(&site_set_scanner);
+                let mut sweep_sites = genome_work_space.find_motif_sites(&sweep_scanner);
 
-                for (refenrece_id, contig) in genome_work_space.contigs.into_iter() {
-                    motif_methylation_pattern(&contig, &motifs, &args.out)?;
+                for (reference_id, contig) in genome_work_space.contigs.into_iter() {
+                    if !pairs.is_empty() {
+                        let sites = motif_sites.remove(&reference_id).unwrap_or_default();
+                        pair_rows.push(motif_methylation_pattern(&contig, &pairs, &sites, args)?);
+                    }
+                    if !site_sets.is_empty() {
+                        let sites = site_set_sites.remove(&reference_id).unwrap_or_default();
+                        site_set_pattern::site_set_methylation_pattern(
+                            &contig,
+                            &site_sets,
+                            &sites,
+                            args.methylation_threshold,
+                            args.coordinates.into(),
+                            &args.out,
+                        )?;
+                    }
+                    if !sweep_motifs.is_empty() {
+                        let sites = sweep_sites.remove(&reference_id).unwrap_or_default();
+                        position_sweep::motif_position_sweep(
+                            &contig,
+                            &sweep_motifs,
+                            &sites,
+                            &args.out,
+                        )?;
+                    }
                 }
             }
             None => {
@@ -65,102 +176,418 @@ pub fn memopair(args: &cli::Cli) -> Result<(), anyhow::Error> {
             break;
         }
     }
+    if !pair_rows.is_empty() {
+        let motif_names: Vec<String> = pairs.iter().map(|spec| spec.name.clone()).collect();
+        run_statistics::write_run_statistics(&pair_rows, &motif_names, args)?;
+    }
+    if let Some(compare_pileup) = &args.compare_pileup {
+        if pairs.is_empty() {
+            warn!("--compare-pileup only compares motif pairs, none were given");
+        } else {
+            differential::differential_hemimethylation(
+                &reference,
+                &pairs,
+                &scanner,
+                compare_pileup,
+                args,
+            )?;
+        }
+    }
     info!("Finished processing in {:?}", global_timer.elapsed());
     Ok(())
 }
 
-fn motif_methylation_pattern(
+/// A site pair to report, as given on the command line.
+#[derive(Debug, Clone)]
+pub struct PairSpec {
+    pub name: String,
+    pub pair: motif::OffsetPair,
+}
+
+impl From<&motif::MotifPair> for PairSpec {
+    fn from(motif_pair: &motif::MotifPair) -> Self {
+        Self {
+            name: motif_pair.to_string(),
+            pair: motif::OffsetPair::from(motif_pair),
+        }
+    }
+}
+
+impl From<motif::OffsetPair> for PairSpec {
+    fn from(pair: motif::OffsetPair) -> Self {
+        Self {
+            name: pair.to_string(),
+            pair,
+        }
+    }
+}
+
+/// A modified base of a motif occurrence and its partner base.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PairedSite {
+    pub motif_idx: usize,
+    /// Bases of the motif occurrence on the positive strand.
+    pub occurrence: Interval,
+    pub site_1: (Position, Strand),
+    pub site_2: (Position, Strand),
+}
+
+impl PairedSite {
+    /// Identity of the site pair independent of which motif or strand it was found from.
+    fn key(&self) -> [(Position, Strand); 2] {
+        let mut key = [self.site_1, self.site_2];
+        key.sort_by_key(|(position, strand)| (*position, *strand == Strand::Negative));
+        key
+    }
+}
+
+/// Pairs every motif site with its partner position.
+///
+/// A site pair found on both strands, as for `TGATC_a_2_a_3` in `TGATCA`, is kept once
+/// for its forward occurrence.
+pub fn paired_sites(
     contig: &sequence::Contig,
-    motifs: &Vec<motif::MotifPair>,
-    out: &str,
-) -> Result<(), anyhow::Error> {
-    let out_path = format!("{}/{}.tsv", out, contig.reference);
-    let mut record_writer = MotifPairRecordWriter::new(&out_path)?;
-    record_writer.write_header()?;
+    motif_idx: usize,
+    pair: &motif::OffsetPair,
+    sites: &MotifSites,
+) -> Vec<PairedSite> {
+    let mut paired = Vec::with_capacity(sites.forward.len() + sites.reverse.len());
+    let partner_strand = pair.partner_strand;
+    let opposite_strand = match partner_strand {
+        Strand::Positive => Strand::Negative,
+        Strand::Negative => Strand::Positive,
+    };
+    let len = pair.motif.sequence.len();
+    let forward_position = pair.motif.position as isize;
+    let reverse_position = (len - 1) as isize - forward_position;
+
+    // Process forward strand
+    for index in sites.forward.iter().map(|&index| Position::new(index)) {
+        let index_2 = match contig.offset_position(index, pair.offset) {
+            Some(i) => i,
+            None => continue,
+        };
+        let occurrence_start = match contig.offset_position(index, -forward_position) {
+            Some(i) => i,
+            None => continue,
+        };
+        paired.push(PairedSite {
+            motif_idx,
+            occurrence: Interval::new(occurrence_start, len),
+            site_1: (index, Strand::Positive),
+            site_2: (index_2, partner_strand),
+        });
+    }
+    // If motif pair is palindromic, the reverse is captured in the reverse complement of the forward motif
+    if pair.is_palindromic() {
+        debug!("Skipping reverse strand, as motifs are palindromic");
+        return paired;
+    }
 
-    for motif in motifs {
-        debug!("Processing motif pair: {:?}", motif);
-        debug!("Processing forward strand");
-        let mod_position_shift =
-            motif.reverse.reverse_complement().unwrap().position as i8 - motif.forward.position as i8;
+    let forward_keys: HashSet<[(Position, Strand); 2]> = paired.iter().map(PairedSite::key).collect();
 
-        // Process forward strand
-        let fwd_indices = match contig.find_motif_indeces(&motif.forward) {
+    // Process reverse strand
+    for index in sites.reverse.iter().map(|&index| Position::new(index)) {
+        let index_2 = match contig.offset_position(index, -pair.offset) {
             Some(i) => i,
             None => continue,
         };
-        for index  in fwd_indices {
-            let index_2 = index as isize + mod_position_shift as isize;
-            if index_2 < 0 {
-                continue;
-            }
-            let record_1 =
-                match contig
-                    .records
-                    .get(&(index, Strand::Positive, motif.forward.mod_type))
-                {
-                    Some(r) => r,
-                    None => continue,
-                };
-            let record_2 = match contig.records.get(&(
-                index_2 as usize,
-                Strand::Negative,
-                motif.reverse.mod_type,
-            )) {
-                Some(r) => r,
-                None => continue,
-            };
-            record_writer.write_record(motif, record_1, record_2)?;
+        let occurrence_start = match contig.offset_position(index, -reverse_position) {
+            Some(i) => i,
+            None => continue,
+        };
+        let site = PairedSite {
+            motif_idx,
+            occurrence: Interval::new(occurrence_start, len),
+            site_1: (index, Strand::Negative),
+            site_2: (index_2, opposite_strand),
+        };
+        if !forward_keys.contains(&site.key()) {
+            paired.push(site);
         }
-        // If motif pair is palindromic, the reverse is captured in the reverse complement of the forward motif
-        if motif.is_palindromic {
-            debug!("Skipping reverse strand, as motifs are palindromic");
-            continue;
+    }
+    paired
+}
+
+/// Picks the motif a site pair found by several motifs is reported for.
+fn assign_site(
+    motif_idxs: &[usize],
+    pairs: &[PairSpec],
+    assignment: &cli::SiteAssignment,
+) -> Option<usize> {
+    match assignment {
+        cli::SiteAssignment::All => None,
+        cli::SiteAssignment::First => motif_idxs.iter().min().copied(),
+        cli::SiteAssignment::MostSpecific => motif_idxs.iter().copied().min_by(|&a, &b| {
+            pairs[a]
+                .pair
+                .motif
+                .match_probability()
+                .total_cmp(&pairs[b].pair.motif.match_probability())
+                .then(a.cmp(&b))
+        }),
+    }
+}
+
+/// Site pairs of all motifs that are reported under `assignment`, each with the motifs
+/// that found it.
+pub fn assigned_sites(
+    contig: &sequence::Contig,
+    pairs: &[PairSpec],
+    motif_sites: &[MotifSites],
+    assignment: &cli::SiteAssignment,
+) -> Vec<(PairedSite, Vec<usize>)> {
+    let mut sites = Vec::new();
+    for (motif_idx, (spec, motif_sites)) in pairs.iter().zip(motif_sites.iter()).enumerate() {
+        debug!("Processing motif pair: {}", spec.name);
+        sites.extend(paired_sites(contig, motif_idx, &spec.pair, motif_sites));
+    }
+
+    // Site pairs found by more than one motif
+    let mut shared_sites: HashMap<[(Position, Strand); 2], Vec<usize>> = HashMap::new();
+    for site in sites.iter() {
+        let motif_idxs = shared_sites.entry(site.key()).or_default();
+        if !motif_idxs.contains(&site.motif_idx) {
+            motif_idxs.push(site.motif_idx);
         }
+    }
 
-        // Process reverse strand
-        debug!("Processing reverse strand");
-        let rev_indices = match contig.find_complement_motif_indeces(&motif.forward) {
-            Some(i) => i,
+    sites
+        .into_iter()
+        .filter(|site| {
+            assign_site(&shared_sites[&site.key()], pairs, assignment)
+                .is_none_or(|owner| owner == site.motif_idx)
+        })
+        .map(|site| (site, shared_sites[&site.key()].clone()))
+        .collect()
+}
+
+fn motif_methylation_pattern(
+    contig: &sequence::Contig,
+    pairs: &[PairSpec],
+    motif_sites: &[MotifSites],
+    args: &cli::Cli,
+) -> Result<run_statistics::ContigRows, anyhow::Error> {
+    let (out, flank) = (&args.out, args.flank);
+    let out_path = format!("{}/{}.tsv", out, contig.reference);
+    let mut record_writer = MotifPairRecordWriter::new(
+        &out_path,
+        flank > 0,
+        args.coordinates.into(),
+        contig.sequence.len(),
+    )?;
+    record_writer.write_header()?;
+    let mut variant_summary = variant_summary::VariantSummary::new();
+    let bootstrap = (args.bootstrap > 0).then(|| motif_summary::Bootstrap::from(args));
+    let mut motif_summary = motif_summary::MotifSummary::new(pairs.len(), bootstrap);
+    let mut context_summary = context_summary::ContextSummary::new(args.methylation_threshold);
+    let mut rows = Vec::new();
+
+    for (site, motif_idxs) in assigned_sites(contig, pairs, motif_sites, &args.site_assignment) {
+        let pair = &pairs[site.motif_idx].pair;
+        let record_1 = match contig.records.get(&(site.site_1.0, site.site_1.1, pair.motif.mod_type)) {
+            Some(r) => r,
+            None => continue,
+        };
+        let record_2 = match contig.records.get(&(site.site_2.0, site.site_2.1, pair.partner_mod_type)) {
+            Some(r) => r,
             None => continue,
         };
-        for index in rev_indices {
-            let key1 = (index, Strand::Negative, motif.forward.mod_type);
-            let record_1 = match contig.records.get(&key1) {
-                Some(r) => r,
-                None => continue,
-            };
-            let key2 = (
-                index - mod_position_shift as usize,
-                Strand::Positive,
-                motif.reverse.mod_type,
+        let shared_with = motif_idxs
+            .iter()
+            .filter(|&&idx| idx != site.motif_idx)
+            .map(|&idx| pairs[idx].name.clone())
+            .collect::<Vec<String>>()
+            .join(",");
+        let occurrence_start = site.occurrence.start();
+        let matched_sequence =
+            contig.occurrence_sequence(occurrence_start, site.occurrence.len(), site.site_1.1);
+        variant_summary.add(site.motif_idx, &matched_sequence, record_1, record_2);
+        motif_summary.add(site.motif_idx, record_1, record_2);
+        let flanks = if flank > 0 {
+            let (upstream, downstream) = contig.flanking_sequences(
+                occurrence_start,
+                site.occurrence.len(),
+                site.site_1.1,
+                flank,
             );
-            let record_2 = match contig.records.get(&key2) {
-                Some(r) => r,
-                None => continue,
-            };
-            record_writer.write_record(motif, record_1, record_2)?;
-        }
+            context_summary.add(site.motif_idx, &upstream, &downstream, record_1, record_2);
+            Some((upstream, downstream))
+        } else {
+            None
+        };
+        let statistics = PairStatistics::new(record_1, record_2, &args.exact_test);
+        rows.push(run_statistics::PairRow {
+            motif_idx: site.motif_idx,
+            p_value: statistics.exact_test.two_sided,
+            site_1: record_1.into(),
+            site_2: record_2.into(),
+        });
+        record_writer.write_record(
+            pair,
+            &site.occurrence,
+            record_1,
+            record_2,
+            &statistics,
+            &shared_with,
+            &matched_sequence,
+            flanks.as_ref(),
+        )?;
     }
     record_writer.flush()?;
-    Ok(())
+    let motif_names: Vec<String> = pairs.iter().map(|spec| spec.name.clone()).collect();
+    variant_summary.write(
+        &format!("{}/{}.variants.tsv", out, contig.reference),
+        &contig.reference,
+        &motif_names,
+    )?;
+    motif_summary.write(
+        &format!("{}/{}.motif_summary.tsv", out, contig.reference),
+        &contig.reference,
+        &motif_names,
+    )?;
+    if flank > 0 {
+        context_summary.write(
+            &format!("{}/{}.context.tsv", out, contig.reference),
+            &contig.reference,
+            &motif_names,
+        )?;
+    }
+    Ok(run_statistics::ContigRows {
+        reference: contig.reference.clone(),
+        rows,
+    })
+}
+
+/// Warns about motif pairs that can report the same modified bases.
+fn log_redundant_motifs(motifs: &[motif::MotifPair]) {
+    for (i, motif_a) in motifs.iter().enumerate() {
+        for motif_b in motifs.iter().skip(i + 1) {
+            if let Some(overlap) = motif_a.overlap_with(motif_b) {
+                warn!(
+                    "Motif pairs {} and {} share sites ({}), see --site-assignment",
+                    motif_a,
+                    motif_b,
+                    overlap
+                );
+            }
+        }
+    }
+}
+
+/// Comparison of the methylation of the two sites of a pair.
+///
+/// The corrected odds ratio and the intervals stay finite when a site has no or only
+/// modified reads, unlike the plain odds ratio. The difference is site 1 minus site 2.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct PairStatistics {
+    methylation_difference: f64,
+    odds_ratio: f64,
+    log_odds_ratio_var: f64,
+    corrected_log_odds_ratio: f64,
+    corrected_log_odds_ratio_var: f64,
+    odds_ratio_mid_p: stats::ConfidenceInterval,
+    difference_wald: stats::ConfidenceInterval,
+    difference_mid_p: stats::ConfidenceInterval,
+    exact_test: stats::ExactTestResult,
+}
+
+impl PairStatistics {
+    fn new(
+        record_1: &pileup::PileupRecord,
+        record_2: &pileup::PileupRecord,
+        exact_test: &cli::ExactTest,
+    ) -> Self {
+        let n_nomod_1 = record_1.n_valid_cov - record_1.n_mod;
+        let mean_mod_1 = record_1.n_mod as f64 / record_1.n_valid_cov as f64;
+        let n_nomod_2 = record_2.n_valid_cov - record_2.n_mod;
+        let mean_mod_2 = record_2.n_mod as f64 / record_2.n_valid_cov as f64;
+
+        let odds_1 = mean_mod_1 / (1.0 - mean_mod_1);
+        let odds_2 = mean_mod_2 / (1.0 - mean_mod_2);
+        let odds_ratio = if odds_2 == 0.0 || odds_1 == 0.0 {
+            f64::NAN
+        } else {
+            odds_1 / odds_2
+        };
+        let log_odds_ratio_var = (1.0 / n_nomod_1 as f64) + (1.0 /record_1.n_mod as f64) + (1.0 / n_nomod_2 as f64)  + (1.0 / record_2.n_mod as f64);
+        let exact_test = match exact_test {
+            cli::ExactTest::Fisher => {
+                stats::fisher_exact(record_1.n_mod, n_nomod_1, record_2.n_mod, n_nomod_2)
+            }
+            cli::ExactTest::Barnard => {
+                stats::barnard_exact(record_1.n_mod, n_nomod_1, record_2.n_mod, n_nomod_2)
+            }
+        };
+        let (corrected_log_odds_ratio, corrected_log_odds_ratio_var) =
+            stats::haldane_anscombe_log_odds_ratio(record_1.n_mod, n_nomod_1, record_2.n_mod, n_nomod_2);
+        Self {
+            methylation_difference: mean_mod_1 - mean_mod_2,
+            odds_ratio,
+            log_odds_ratio_var,
+            corrected_log_odds_ratio,
+            corrected_log_odds_ratio_var,
+            odds_ratio_mid_p: stats::mid_p_odds_ratio_interval(
+                record_1.n_mod,
+                n_nomod_1,
+                record_2.n_mod,
+                n_nomod_2,
+            ),
+            difference_wald: stats::difference_wald_interval(
+                record_1.n_mod,
+                record_1.n_valid_cov,
+                record_2.n_mod,
+                record_2.n_valid_cov,
+            ),
+            difference_mid_p: stats::difference_mid_p_interval(
+                record_1.n_mod,
+                record_1.n_valid_cov,
+                record_2.n_mod,
+                record_2.n_valid_cov,
+            ),
+            exact_test,
+        }
+    }
+
+    fn z_score(&self) -> f64 {
+        self.odds_ratio.ln() / self.log_odds_ratio_var.sqrt()
+    }
+
+    /// Wald interval of the corrected odds ratio.
+    fn odds_ratio_wald(&self) -> stats::ConfidenceInterval {
+        stats::ConfidenceInterval::wald(self.corrected_log_odds_ratio, self.corrected_log_odds_ratio_var)
+            .map(f64::exp)
+    }
 }
 
 #[derive(Debug)]
 struct MotifPairRecordWriter {
     csv_writer: csv::Writer<File>,
+    flanks: bool,
+    coordinates: CoordinateSystem,
+    contig_len: usize,
 }
 
 impl MotifPairRecordWriter {
-    pub fn new(out_path: &str) -> Result<Self, anyhow::Error> {
+    pub fn new(
+        out_path: &str,
+        flanks: bool,
+        coordinates: CoordinateSystem,
+        contig_len: usize,
+    ) -> Result<Self, anyhow::Error> {
         let csv_writer = csv::WriterBuilder::new()
             .delimiter(b'\t')
             .from_path(out_path)?;
-        Ok(Self { csv_writer })
+        Ok(Self {
+            csv_writer,
+            flanks,
+            coordinates,
+            contig_len,
+        })
     }
 
     pub fn write_header(&mut self) -> Result<(), anyhow::Error> {
-        self.csv_writer.write_record(&[
+        let mut header = vec![
             "contig_id",
             "motif_start_position",
             "strand",
@@ -182,60 +609,97 @@ impl MotifPairRecordWriter {
             "log_odds_ratio",
             "log_odds_ratio_var",
             "z_score",
-        ])?;
+            "corrected_odds_ratio",
+            "corrected_log_odds_ratio",
+            "corrected_log_odds_ratio_var",
+            "odds_ratio_wald_low",
+            "odds_ratio_wald_high",
+            "odds_ratio_mid_p_low",
+            "odds_ratio_mid_p_high",
+            "methylation_difference_1_2",
+            "difference_wald_low",
+            "difference_wald_high",
+            "difference_mid_p_low",
+            "difference_mid_p_high",
+            "p_value",
+            "p_value_greater",
+            "p_value_less",
+            "shared_with",
+            "matched_sequence",
+            "motif_end_position",
+        ];
+        if self.flanks {
+            header.extend(["upstream_flank", "downstream_flank"]);
+        }
+        self.csv_writer.write_record(header)?;
         Ok(())
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn write_record(
         &mut self,
-        motif_pair: &motif::MotifPair,
+        pair: &motif::OffsetPair,
+        occurrence: &Interval,
         record_1: &pileup::PileupRecord,
         record_2: &pileup::PileupRecord,
+        statistics: &PairStatistics,
+        shared_with: &str,
+        matched_sequence: &str,
+        flanks: Option<&(String, String)>,
     ) -> Result<(), anyhow::Error> {
-        let start_position = record_1.position as u32 - motif_pair.forward.position as u32;
-        let n_nomod_1 = record_1.n_valid_cov - record_1.n_mod;
-        let mean_mod_1 = record_1.n_mod as f64 / record_1.n_valid_cov as f64;
-        let n_nomod_2 = record_2.n_valid_cov - record_2.n_mod;
-        let mean_mod_2 = record_2.n_mod as f64 / record_2.n_valid_cov as f64;
-        let motif_2_mod_pos = motif_pair.reverse.reverse_complement().unwrap().position;
-
-        let methylation_diff = mean_mod_1 - mean_mod_2;
-        let abs_methylation_diff = methylation_diff.abs();
-        let odds_1 = mean_mod_1 / (1.0 - mean_mod_1);
-        let odds_2 = mean_mod_2 / (1.0 - mean_mod_2);
-        let odds_ratio: f64;
-        if odds_2 == 0.0 || odds_1 == 0.0 {
-            odds_ratio = f64::NAN;
-        } else {
-            odds_ratio = odds_1 / odds_2;
-        }
-
-        let log_odds_ratio = odds_ratio.ln();
-        let log_odds_ratio_var = (1.0 / n_nomod_1 as f64) + (1.0 /record_1.n_mod as f64) + (1.0 / n_nomod_2 as f64)  + (1.0 / record_2.n_mod as f64);
-        let z_score = log_odds_ratio / log_odds_ratio_var.sqrt();
-        self.csv_writer.write_record(&[
+        let motif_2_mod_pos = pair.partner_position();
+        let odds_ratio_wald = statistics.odds_ratio_wald();
+        let (start_position, end_position) =
+            self.coordinates.interval(occurrence, self.contig_len);
+        let mut row = vec![
             record_1.reference.clone(),
             start_position.to_string(),
             record_1.strand.to_string(),
-            motif_pair.forward.sequence_string(),
-            motif_pair.forward.position.to_string(),
-            motif_pair.forward.mod_type.to_string().to_string(),
-            record_1.position.to_string(),
+            pair.motif.sequence_string(),
+            pair.motif.position.to_string(),
+            pair.motif.mod_type.to_string().to_string(),
+            self.coordinates
+                .position(Position::new(record_1.position))
+                .to_string(),
             record_1.n_mod.to_string(),
-            n_nomod_1.to_string(),
+            (record_1.n_valid_cov - record_1.n_mod).to_string(),
             record_1.n_diff.to_string(),
             motif_2_mod_pos.to_string(),
-            motif_pair.reverse.mod_type.to_string().to_string(),
-            record_2.position.to_string(),
+            pair.partner_mod_type.to_string().to_string(),
+            self.coordinates
+                .position(Position::new(record_2.position))
+                .to_string(),
             record_2.n_mod.to_string(),
-            n_nomod_2.to_string(),
+            (record_2.n_valid_cov - record_2.n_mod).to_string(),
             record_2.n_diff.to_string(),
-            abs_methylation_diff.to_string(),
-            odds_ratio.to_string(),
-            log_odds_ratio.to_string(),
-            log_odds_ratio_var.to_string(),
-            z_score.to_string(),
-        ])?;
+            statistics.methylation_difference.abs().to_string(),
+            statistics.odds_ratio.to_string(),
+            statistics.odds_ratio.ln().to_string(),
+            statistics.log_odds_ratio_var.to_string(),
+            statistics.z_score().to_string(),
+            statistics.corrected_log_odds_ratio.exp().to_string(),
+            statistics.corrected_log_odds_ratio.to_string(),
+            statistics.corrected_log_odds_ratio_var.to_string(),
+            odds_ratio_wald.low.to_string(),
+            odds_ratio_wald.high.to_string(),
+            statistics.odds_ratio_mid_p.low.to_string(),
+            statistics.odds_ratio_mid_p.high.to_string(),
+            statistics.methylation_difference.to_string(),
+            statistics.difference_wald.low.to_string(),
+            statistics.difference_wald.high.to_string(),
+            statistics.difference_mid_p.low.to_string(),
+            statistics.difference_mid_p.high.to_string(),
+            statistics.exact_test.two_sided.to_string(),
+            statistics.exact_test.greater.to_string(),
+            statistics.exact_test.less.to_string(),
+            shared_with.to_string(),
+            matched_sequence.to_string(),
+            end_position.to_string(),
+        ];
+        if let Some((upstream, downstream)) = flanks {
+            row.extend([upstream.clone(), downstream.clone()]);
+        }
+        self.csv_writer.write_record(&row)?;
         Ok(())
     }
     pub fn flush(&mut self) -> Result<(), anyhow::Error> {
@@ -246,29 +710,133 @@ impl MotifPairRecordWriter {
 
 
 
-fn parse_motif_pair_string(motif_pair_string: String) -> Result<motif::MotifPair, anyhow::Error> {
-    let parts: Vec<&str> = motif_pair_string.split('_').collect();
-    if parts.len() != 5 {
-        bail!("Invalid motif pair string: {}", motif_pair_string);
-    }
-    let sequence_1 = parts[0];
-    let mod_type_1 = parts[1];
-    let position_1 = parts[2].parse::<u8>()?;
-    let motif_1 = motif::Motif::new(sequence_1, mod_type_1, position_1)?;
-    let sequence_2 = motif_1.reverse_complement_sequence();
-    let mod_type_2 = parts[3];
-    let position_2 = parts[4].parse::<u8>()?;
-    let position_2 = sequence_2.len() as u8 - position_2 - 1;
-    let motif_2 = motif::Motif::new(sequence_2.as_str(), mod_type_2, position_2)?;
-    let pair = motif::MotifPair::new(motif_1, motif_2)?;
-    Ok(pair)
-}
-
+/// Parses the motif pairs given on the command line, dropping duplicates, including
+/// pairs written from the other strand. The pairs keep the orientation given first.
 fn parse_motif_pair_strings(
     motif_pair_strings: Vec<String>,
 ) -> Result<Vec<motif::MotifPair>, anyhow::Error> {
-    motif_pair_strings
-        .into_iter()
-        .map(|s| parse_motif_pair_string(s))
-        .collect()
+    let mut motif_pairs: Vec<motif::MotifPair> = Vec::with_capacity(motif_pair_strings.len());
+    let mut canonical_pairs: Vec<motif::MotifPair> = Vec::with_capacity(motif_pair_strings.len());
+    for motif_pair_string in motif_pair_strings {
+        let (pair, inference) = motif::MotifPair::parse(&motif_pair_string)?;
+        if let Some(inference) = inference {
+            info!(
+                "Inferred partner of {}: {} ({})",
+                motif_pair_string, pair, inference
+            );
+        }
+        let canonical = pair.clone().canonical();
+        if let Some(i) = canonical_pairs.iter().position(|other| *other == canonical) {
+            warn!(
+                "Ignoring {}, it is the same motif pair as {}",
+                motif_pair_string, motif_pairs[i]
+            );
+            continue;
+        }
+        canonical_pairs.push(canonical);
+        motif_pairs.push(pair);
+    }
+    Ok(motif_pairs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::record;
+
+    fn pair(motif: &str) -> motif::MotifPair {
+        motif.parse().unwrap()
+    }
+
+    #[test]
+    fn test_paired_sites_shared_key() {
+        let contig = sequence::Contig::new("test", "AATGATCAAA");
+        let motifs = [pair("GATC_a_1"), pair("TGATC_a_2_a_3")];
+        let scanner = MotifScanner::new(motifs.iter().map(|pair| &pair.forward)).unwrap();
+        let motif_sites = contig.scan(&scanner);
+
+        let gatc = paired_sites(&contig, 0, &(&motifs[0]).into(), &motif_sites[0]);
+        assert_eq!(gatc.len(), 1);
+        assert_eq!(gatc[0].site_1, (Position::new(4), Strand::Positive));
+        assert_eq!(gatc[0].site_2, (Position::new(5), Strand::Negative));
+
+        // TGATCA contains the motif on both strands, the site pair is kept once
+        let tgatc = paired_sites(&contig, 1, &(&motifs[1]).into(), &motif_sites[1]);
+        assert_eq!(motif_sites[1].reverse, vec![5]);
+        assert_eq!(tgatc.len(), 1);
+        assert_eq!(tgatc[0].site_1, (Position::new(4), Strand::Positive));
+        assert_eq!(tgatc[0].key(), gatc[0].key());
+        assert_eq!(tgatc[0].occurrence, Interval::new(Position::new(2), 5));
+    }
+
+    #[test]
+    fn test_paired_sites_offset() {
+        let contig = sequence::Contig::new("test", "AATGATCAAA");
+        let pair: motif::OffsetPair = "GATC_a_1_-3_+_a".parse().unwrap();
+        let scanner = MotifScanner::new([&pair.motif]).unwrap();
+        let motif_sites = contig.scan(&scanner);
+
+        // GATC is found on both strands, pairing each A with the A three bases upstream
+        let sites = paired_sites(&contig, 0, &pair, &motif_sites[0]);
+        assert_eq!(sites.len(), 2);
+        assert_eq!(sites[0].site_1, (Position::new(4), Strand::Positive));
+        assert_eq!(sites[0].site_2, (Position::new(1), Strand::Positive));
+        assert_eq!(sites[1].site_1, (Position::new(5), Strand::Negative));
+        assert_eq!(sites[1].site_2, (Position::new(8), Strand::Negative));
+    }
+
+    #[test]
+    fn test_assign_site() {
+        let motifs = [pair("GATC_a_1"), pair("RGATCY_a_2"), pair("CCWGG_m_1")]
+            .iter()
+            .map(PairSpec::from)
+            .collect::<Vec<PairSpec>>();
+        assert_eq!(assign_site(&[0, 1], &motifs, &cli::SiteAssignment::All), None);
+        assert_eq!(assign_site(&[0, 1], &motifs, &cli::SiteAssignment::First), Some(0));
+        assert_eq!(
+            assign_site(&[0, 1], &motifs, &cli::SiteAssignment::MostSpecific),
+            Some(1)
+        );
+        assert_eq!(
+            assign_site(&[2], &motifs, &cli::SiteAssignment::MostSpecific),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn test_pair_statistics() {
+        // Fully methylated against unmethylated site
+        let statistics = PairStatistics::new(&record(10), &record(0), &cli::ExactTest::Fisher);
+        assert!(statistics.odds_ratio.is_nan());
+        assert!(statistics.corrected_log_odds_ratio.is_finite());
+        let wald = statistics.odds_ratio_wald();
+        assert!(wald.low > 1.0 && wald.high.is_finite());
+        assert_eq!(statistics.odds_ratio_mid_p.high, f64::INFINITY);
+        assert_eq!(statistics.methylation_difference, 1.0);
+        assert!(statistics.difference_wald.low > 0.0);
+        assert!(statistics.difference_mid_p.low > 0.0);
+
+        let statistics = PairStatistics::new(&record(3), &record(8), &cli::ExactTest::Fisher);
+        assert!(statistics.difference_mid_p.high < 0.0);
+        assert!(statistics.odds_ratio_mid_p.low < statistics.odds_ratio);
+        assert!(statistics.odds_ratio < statistics.odds_ratio_mid_p.high);
+    }
+
+    #[test]
+    fn test_parse_motif_pair_strings() {
+        let motifs = parse_motif_pair_strings(vec![
+            "GATC_a_1".to_string(),
+            "CCWGG_m_1".to_string(),
+            "GATC_a_1_a_2".to_string(),
+            "TGATC_a_2_a_3".to_string(),
+            "GATCA_a_1_a_2".to_string(),
+        ])
+        .unwrap();
+        assert_eq!(motifs.len(), 3);
+        assert_eq!(motifs[0].to_string(), "GATC_a_1_a_2");
+        assert_eq!(motifs[1].to_string(), "CCWGG_m_1_m_3");
+        assert_eq!(motifs[2].to_string(), "TGATC_a_2_a_3");
+
+        assert!(parse_motif_pair_strings(vec!["GATC_a_2".to_string()]).is_err());
+    }
 }
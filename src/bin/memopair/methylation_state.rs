@@ -0,0 +1,295 @@
+use crate::{
+    cli,
+    empirical_bayes::{site_posterior, MotifPrior},
+    run_statistics::{ContigRows, PairRow, RunColumns, SiteCounts},
+};
+use ahash::{HashMap, HashMapExt};
+use anyhow::Result;
+use std::fmt;
+use utils::{modtype::ModType, strand::Strand};
+
+/// Methylation state of a site pair.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MethylationState {
+    FullyMethylated,
+    /// Only site 1, the modified base of the motif, is methylated.
+    Hemimethylated1,
+    /// Only site 2, the partner base, is methylated.
+    Hemimethylated2,
+    Unmethylated,
+    /// At least one site cannot be called either way.
+    Ambiguous,
+}
+
+impl MethylationState {
+    pub const ALL: [MethylationState; 5] = [
+        MethylationState::FullyMethylated,
+        MethylationState::Hemimethylated1,
+        MethylationState::Hemimethylated2,
+        MethylationState::Unmethylated,
+        MethylationState::Ambiguous,
+    ];
+
+    /// Strand of the methylated site of a hemimethylated pair.
+    pub fn hemimethylated_strand(&self, row: &PairRow) -> Option<Strand> {
+        match self {
+            MethylationState::Hemimethylated1 => Some(row.site_1.strand),
+            MethylationState::Hemimethylated2 => Some(row.site_2.strand),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for MethylationState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MethylationState::FullyMethylated => write!(f, "fully_methylated"),
+            MethylationState::Hemimethylated1 => write!(f, "hemimethylated_1"),
+            MethylationState::Hemimethylated2 => write!(f, "hemimethylated_2"),
+            MethylationState::Unmethylated => write!(f, "unmethylated"),
+            MethylationState::Ambiguous => write!(f, "ambiguous"),
+        }
+    }
+}
+
+/// Call of a single site.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SiteState {
+    Methylated,
+    Unmethylated,
+    Ambiguous,
+}
+
+/// Cutoffs for calling sites methylated or unmethylated, see the `--state-*` options.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StateThresholds {
+    pub method: cli::StateMethod,
+    pub unmethylated_fraction: f64,
+    pub methylated_fraction: f64,
+    pub methylation_threshold: f64,
+    pub state_probability: f64,
+}
+
+impl From<&cli::Cli> for StateThresholds {
+    fn from(args: &cli::Cli) -> Self {
+        Self {
+            method: args.state_method.clone(),
+            unmethylated_fraction: args.unmethylated_fraction,
+            methylated_fraction: args.methylated_fraction,
+            methylation_threshold: args.methylation_threshold,
+            state_probability: args.state_probability,
+        }
+    }
+}
+
+fn site_state(
+    site: &SiteCounts,
+    motif_idx: usize,
+    priors: &HashMap<(usize, ModType), MotifPrior>,
+    thresholds: &StateThresholds,
+) -> SiteState {
+    let (value, methylated, unmethylated) = match thresholds.method {
+        cli::StateMethod::Fraction => (
+            site.n_mod as f64 / site.n_valid_cov as f64,
+            thresholds.methylated_fraction,
+            thresholds.unmethylated_fraction,
+        ),
+        // Sites without a prior are left ambiguous
+        cli::StateMethod::Posterior => (
+            site_posterior(priors, motif_idx, site).map_or(f64::NAN, |posterior| {
+                posterior.probability_at_least(thresholds.methylation_threshold)
+            }),
+            thresholds.state_probability,
+            1.0 - thresholds.state_probability,
+        ),
+    };
+    if value >= methylated {
+        SiteState::Methylated
+    } else if value <= unmethylated {
+        SiteState::Unmethylated
+    } else {
+        SiteState::Ambiguous
+    }
+}
+
+/// State of a site pair, labelling hemimethylation by site rather than strand so that
+/// pairs with the partner on the same strand are told apart.
+fn pair_state(site_1: SiteState, site_2: SiteState) -> MethylationState {
+    match (site_1, site_2) {
+        (SiteState::Methylated, SiteState::Methylated) => MethylationState::FullyMethylated,
+        (SiteState::Unmethylated, SiteState::Unmethylated) => MethylationState::Unmethylated,
+        (SiteState::Methylated, SiteState::Unmethylated) => MethylationState::Hemimethylated1,
+        (SiteState::Unmethylated, SiteState::Methylated) => MethylationState::Hemimethylated2,
+        _ => MethylationState::Ambiguous,
+    }
+}
+
+/// Methylation state of every row, indexed by contig and row.
+pub fn classify_rows(
+    contigs: &[ContigRows],
+    priors: &HashMap<(usize, ModType), MotifPrior>,
+    thresholds: &StateThresholds,
+) -> Vec<Vec<MethylationState>> {
+    contigs
+        .iter()
+        .map(|contig| {
+            contig
+                .rows
+                .iter()
+                .map(|row| {
+                    let state_1 = site_state(&row.site_1, row.motif_idx, priors, thresholds);
+                    let state_2 = site_state(&row.site_2, row.motif_idx, priors, thresholds);
+                    pair_state(state_1, state_2)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// State of every row, with the strand of the methylated site of hemimethylated pairs.
+pub fn state_columns(contigs: &[ContigRows], states: &[Vec<MethylationState>]) -> RunColumns {
+    RunColumns {
+        names: vec!["methylation_state", "hemimethylated_strand"],
+        values: contigs
+            .iter()
+            .zip(states.iter())
+            .map(|(contig, states)| {
+                contig
+                    .rows
+                    .iter()
+                    .zip(states.iter())
+                    .map(|(row, state)| {
+                        let strand = state.hemimethylated_strand(row);
+                        vec![state.to_string(), strand.map_or(String::new(), |s| s.to_string())]
+                    })
+                    .collect()
+            })
+            .collect(),
+    }
+}
+
+/// Writes the number of site pairs in each state per contig and motif, followed by the
+/// hemimethylated pairs split by the strand of the methylated site.
+pub fn write_state_counts(
+    out_path: &str,
+    contigs: &[ContigRows],
+    states: &[Vec<MethylationState>],
+    motif_names: &[String],
+) -> Result<(), anyhow::Error> {
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(b'\t')
+        .from_path(out_path)?;
+    let mut header = vec!["contig_id".to_string(), "motif".to_string()];
+    header.extend(MethylationState::ALL.iter().map(|state| format!("n_{}", state)));
+    header.extend(["n_hemimethylated_plus", "n_hemimethylated_minus"].map(String::from));
+    writer.write_record(&header)?;
+
+    for (contig, states) in contigs.iter().zip(states.iter()) {
+        let mut counts: HashMap<(usize, MethylationState), usize> = HashMap::new();
+        let mut strand_counts: HashMap<(usize, Strand), usize> = HashMap::new();
+        for (row, state) in contig.rows.iter().zip(states.iter()) {
+            *counts.entry((row.motif_idx, *state)).or_default() += 1;
+            if let Some(strand) = state.hemimethylated_strand(row) {
+                *strand_counts.entry((row.motif_idx, strand)).or_default() += 1;
+            }
+        }
+        for (motif_idx, motif_name) in motif_names.iter().enumerate() {
+            let mut record = vec![contig.reference.clone(), motif_name.clone()];
+            record.extend(MethylationState::ALL.iter().map(|state| {
+                counts
+                    .get(&(motif_idx, *state))
+                    .copied()
+                    .unwrap_or(0)
+                    .to_string()
+            }));
+            record.extend([Strand::Positive, Strand::Negative].map(|strand| {
+                strand_counts
+                    .get(&(motif_idx, strand))
+                    .copied()
+                    .unwrap_or(0)
+                    .to_string()
+            }));
+            writer.write_record(&record)?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn thresholds(method: cli::StateMethod) -> StateThresholds {
+        StateThresholds {
+            method,
+            unmethylated_fraction: 0.2,
+            methylated_fraction: 0.8,
+            methylation_threshold: 0.5,
+            state_probability: 0.95,
+        }
+    }
+
+    fn site(strand: Strand, n_mod: u32, n_valid_cov: u32) -> SiteCounts {
+        SiteCounts {
+            strand,
+            mod_type: ModType::SixMA,
+            n_mod,
+            n_valid_cov,
+        }
+    }
+
+    #[test]
+    fn test_site_and_pair_states() {
+        let priors = HashMap::new();
+        let fraction = thresholds(cli::StateMethod::Fraction);
+        let state = |n_mod| site_state(&site(Strand::Positive, n_mod, 10), 0, &priors, &fraction);
+        assert_eq!(state(9), SiteState::Methylated);
+        assert_eq!(state(8), SiteState::Methylated);
+        assert_eq!(state(5), SiteState::Ambiguous);
+        assert_eq!(state(2), SiteState::Unmethylated);
+
+        let (methylated, unmethylated) = (SiteState::Methylated, SiteState::Unmethylated);
+        assert_eq!(pair_state(methylated, methylated), MethylationState::FullyMethylated);
+        assert_eq!(pair_state(unmethylated, methylated), MethylationState::Hemimethylated2);
+        assert_eq!(pair_state(methylated, unmethylated), MethylationState::Hemimethylated1);
+        assert_eq!(pair_state(SiteState::Ambiguous, methylated), MethylationState::Ambiguous);
+        assert_eq!(MethylationState::Hemimethylated1.to_string(), "hemimethylated_1");
+    }
+
+    #[test]
+    fn test_classify_rows_posterior() {
+        // Sites split into methylated and unmethylated ones, and one site with two reads
+        let mut rows: Vec<_> = (0..11)
+            .map(|i| crate::run_statistics::PairRow {
+                motif_idx: 0,
+                p_value: 1.0,
+                site_1: site(Strand::Positive, if i < 5 { 20 } else { 0 }, 20),
+                site_2: SiteCounts {
+                    mod_type: ModType::FiveMC,
+                    ..site(Strand::Negative, 0, 30)
+                },
+            })
+            .collect();
+        rows[10].site_1 = site(Strand::Positive, 1, 2);
+        let contigs = [ContigRows {
+            reference: "contig_1".to_string(),
+            rows,
+        }];
+        let priors = crate::empirical_bayes::fit_priors(&contigs);
+
+        let states = classify_rows(&contigs, &priors, &thresholds(cli::StateMethod::Posterior));
+        assert_eq!(states[0][0], MethylationState::Hemimethylated1);
+        assert_eq!(states[0][5], MethylationState::Unmethylated);
+        assert_eq!(states[0][10], MethylationState::Ambiguous);
+        let states = classify_rows(&contigs, &priors, &thresholds(cli::StateMethod::Fraction));
+        assert_eq!(states[0][0], MethylationState::Hemimethylated1);
+        assert_eq!(states[0][10], MethylationState::Ambiguous);
+
+        let columns = state_columns(&contigs, &states);
+        assert_eq!(columns.values[0][0], vec!["hemimethylated_1", "+"]);
+        assert_eq!(columns.values[0][5], vec!["unmethylated", ""]);
+        let state = MethylationState::Hemimethylated2;
+        assert_eq!(state.hemimethylated_strand(&contigs[0].rows[0]), Some(Strand::Negative));
+    }
+}
@@ -0,0 +1,170 @@
+use crate::sequence;
+use ahash::{HashMap, HashMapExt};
+use anyhow::Result;
+use log::info;
+use std::{collections::hash_map::Entry, fs::File};
+use utils::{
+    markov::MarkovBackground,
+    motif::{Motif, MotifLike},
+    scanner::{MotifScanner, MotifSites},
+};
+
+/// Observed and expected occurrences of a motif on both strands of a contig.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Representation {
+    order: usize,
+    n_observed: usize,
+    n_expected: f64,
+    z_score: f64,
+}
+
+/// Markov order used for `motif`, capped at the motif length minus two so that the motif
+/// is not predicted from its own word frequencies.
+fn motif_order(motif: &Motif, order: usize) -> usize {
+    order.min(motif.sequence.len().saturating_sub(2))
+}
+
+/// Compares the occurrences of `motif`, found at `sites`, with those expected from the
+/// contig `background`, which should be of order [`motif_order`].
+///
+/// Palindromic motifs are counted once per site. Expected counts assume a binomial
+/// number of occurrences, giving the variance of the z-score.
+fn motif_representation(
+    contig: &sequence::Contig,
+    motif: &Motif,
+    sites: &MotifSites,
+    background: &MarkovBackground,
+) -> Representation {
+    let len = motif.sequence.len();
+    let n_positions = if contig.circular {
+        contig.sequence.len()
+    } else {
+        contig.sequence.len().saturating_sub(len - 1)
+    } as f64;
+
+    let palindromic = motif.sequence_string() == motif.reverse_complement_sequence();
+    let mut n_observed = sites.forward.len();
+    let mut strands = vec![motif.sequence.clone()];
+    if !palindromic {
+        n_observed += sites.reverse.len();
+        strands.push(motif.sequence.iter().rev().map(|b| b.complement()).collect());
+    }
+    let (mut n_expected, mut variance) = (0.0, 0.0);
+    for sequence in strands.iter() {
+        let probability = background.motif_probability(sequence);
+        n_expected += n_positions * probability;
+        variance += n_positions * probability * (1.0 - probability);
+    }
+    Representation {
+        order: background.order(),
+        n_observed,
+        n_expected,
+        z_score: (n_observed as f64 - n_expected) / variance.sqrt(),
+    }
+}
+
+/// Writes the representation of every motif in every contig of the reference.
+///
+/// The background of a contig is estimated once for each order used by the motifs.
+pub fn write_motif_representation(
+    reference: &HashMap<String, sequence::Contig>,
+    motifs: &[&Motif],
+    order: usize,
+    out: &str,
+) -> Result<(), anyhow::Error> {
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(b'\t')
+        .from_path(format!("{}/motif_representation.tsv", out))?;
+    write_header(&mut writer)?;
+    let scanner = MotifScanner::new(motifs.iter().copied())?;
+
+    let mut contig_ids: Vec<&String> = reference.keys().collect();
+    contig_ids.sort();
+    for contig_id in contig_ids {
+        let contig = &reference[contig_id];
+        let motif_sites = contig.scan(&scanner);
+        let mut backgrounds: HashMap<usize, MarkovBackground> = HashMap::new();
+        for motif in motifs.iter() {
+            if let Entry::Vacant(entry) = backgrounds.entry(motif_order(motif, order)) {
+                let background = MarkovBackground::from_sequence(
+                    &contig.sequence,
+                    *entry.key(),
+                    contig.circular,
+                )?;
+                entry.insert(background);
+            }
+        }
+        for (motif, sites) in motifs.iter().zip(motif_sites.iter()) {
+            let background = &backgrounds[&motif_order(motif, order)];
+            let representation = motif_representation(contig, motif, sites, background);
+            writer.write_record(&[
+                contig_id.clone(),
+                motif.sequence_string(),
+                representation.order.to_string(),
+                representation.n_observed.to_string(),
+                representation.n_expected.to_string(),
+                (representation.n_observed as f64 / representation.n_expected).to_string(),
+                representation.z_score.to_string(),
+            ])?;
+        }
+    }
+    writer.flush()?;
+    info!("Wrote motif representation of {} contigs", reference.len());
+    Ok(())
+}
+
+fn write_header(writer: &mut csv::Writer<File>) -> Result<(), anyhow::Error> {
+    writer.write_record([
+        "contig_id",
+        "motif_sequence",
+        "markov_order",
+        "n_observed",
+        "n_expected",
+        "observed_expected_ratio",
+        "z_score",
+    ])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scan(contig: &sequence::Contig, motif: &Motif) -> MotifSites {
+        contig.scan(&MotifScanner::new([motif]).unwrap()).remove(0)
+    }
+
+    fn background(contig: &sequence::Contig, motif: &Motif, order: usize) -> MarkovBackground {
+        let order = motif_order(motif, order);
+        MarkovBackground::from_sequence(&contig.sequence, order, contig.circular).unwrap()
+    }
+
+    #[test]
+    fn test_motif_representation() {
+        // GATC occurs in every repeat, far more often than the base composition predicts
+        let contig = sequence::Contig::new("test", &"GACTTAGCGATCCATG".repeat(20));
+        let motif = Motif::new("GATC", "a", 1).unwrap();
+        let sites = scan(&contig, &motif);
+        let representation =
+            motif_representation(&contig, &motif, &sites, &background(&contig, &motif, 0));
+        assert_eq!(representation.order, 0);
+        assert_eq!(representation.n_observed, 20);
+        // 317 positions with each base at frequency 1/4
+        assert!((representation.n_expected - 317.0 / 256.0).abs() < 1e-9);
+        assert!(representation.z_score > 0.0);
+
+        // The order is capped at the motif length minus two
+        assert_eq!(motif_order(&motif, 5), 2);
+        let representation =
+            motif_representation(&contig, &motif, &sites, &background(&contig, &motif, 5));
+        assert_eq!(representation.order, 2);
+
+        // Non-palindromic motifs are counted on both strands
+        let motif = Motif::new("GACT", "a", 1).unwrap();
+        let sites = scan(&contig, &motif);
+        let representation =
+            motif_representation(&contig, &motif, &sites, &background(&contig, &motif, 0));
+        assert_eq!(representation.n_observed, 20);
+        assert!((representation.n_expected - 2.0 * 317.0 / 256.0).abs() < 1e-9);
+    }
+}
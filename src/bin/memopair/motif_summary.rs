@@ -0,0 +1,341 @@
+use crate::cli;
+use anyhow::Result;
+use std::fs::File;
+use utils::{pileup::PileupRecord, random::SplitMix64, stats};
+
+/// Statistics with bootstrap intervals, in the order of their columns.
+const BOOTSTRAPPED: [&str; 5] = [
+    "mean_methylation_1",
+    "mean_methylation_2",
+    "mean_difference",
+    "median_difference",
+    "mh_odds_ratio",
+];
+
+/// Settings of the bootstrap of the motif summaries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bootstrap {
+    pub replicates: usize,
+    /// Also draw the modified reads of every resampled site from a binomial.
+    pub resample_reads: bool,
+    pub seed: u64,
+}
+
+impl From<&cli::Cli> for Bootstrap {
+    fn from(args: &cli::Cli) -> Self {
+        Self {
+            replicates: args.bootstrap,
+            resample_reads: args.bootstrap_reads,
+            seed: args.seed,
+        }
+    }
+}
+
+/// Site pairs reported for a motif, kept to summarise them once all are known.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct MotifSitePairs {
+    methylation_1: Vec<f64>,
+    methylation_2: Vec<f64>,
+    /// Modified and unmodified reads of both sites as `[n_mod_1, n_nomod_1, n_mod_2, n_nomod_2]`.
+    tables: Vec<[u32; 4]>,
+}
+
+/// Aggregate statistics of the site pairs of a motif.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct MotifStats {
+    n_sites: usize,
+    mean_methylation_1: f64,
+    mean_methylation_2: f64,
+    mean_difference: f64,
+    median_difference: f64,
+    strand_correlation: f64,
+    mantel_haenszel: stats::MantelHaenszel,
+    breslow_day: f64,
+    breslow_day_df: usize,
+}
+
+impl MotifSitePairs {
+    fn push(&mut self, table: [u32; 4]) {
+        let [n_mod_1, n_nomod_1, n_mod_2, n_nomod_2] = table;
+        self.methylation_1
+            .push(n_mod_1 as f64 / (n_mod_1 + n_nomod_1) as f64);
+        self.methylation_2
+            .push(n_mod_2 as f64 / (n_mod_2 + n_nomod_2) as f64);
+        self.tables.push(table);
+    }
+
+    /// Sites drawn with replacement, with their modified reads drawn from a binomial with
+    /// the observed fraction if `resample_reads`.
+    fn resample(&self, rng: &mut SplitMix64, resample_reads: bool) -> Self {
+        let mut resampled = Self::default();
+        for _ in 0..self.tables.len() {
+            let [n_mod_1, n_nomod_1, n_mod_2, n_nomod_2] = self.tables[rng.below(self.tables.len())];
+            if resample_reads {
+                let (n_1, n_2) = (n_mod_1 + n_nomod_1, n_mod_2 + n_nomod_2);
+                let n_mod_1 = rng.binomial(n_1, n_mod_1 as f64 / n_1 as f64);
+                let n_mod_2 = rng.binomial(n_2, n_mod_2 as f64 / n_2 as f64);
+                resampled.push([n_mod_1, n_1 - n_mod_1, n_mod_2, n_2 - n_mod_2]);
+            } else {
+                resampled.push([n_mod_1, n_nomod_1, n_mod_2, n_nomod_2]);
+            }
+        }
+        resampled
+    }
+
+    /// Percentile intervals of the [`BOOTSTRAPPED`] statistics, each with the number of
+    /// replicates it is based on, leaving out those where the statistic is `NaN`.
+    fn bootstrap_intervals(
+        &self,
+        bootstrap: &Bootstrap,
+        rng: &mut SplitMix64,
+    ) -> Vec<(stats::ConfidenceInterval, usize)> {
+        let mut replicates = vec![Vec::with_capacity(bootstrap.replicates); BOOTSTRAPPED.len()];
+        for _ in 0..bootstrap.replicates {
+            let stats = self.resample(rng, bootstrap.resample_reads).stats();
+            for (values, value) in replicates.iter_mut().zip([
+                stats.mean_methylation_1,
+                stats.mean_methylation_2,
+                stats.mean_difference,
+                stats.median_difference,
+                stats.mantel_haenszel.odds_ratio,
+            ]) {
+                values.push(value);
+            }
+        }
+        replicates
+            .iter()
+            .map(|values| {
+                let n_valid = values.iter().filter(|value| !value.is_nan()).count();
+                (stats::percentile_interval(values), n_valid)
+            })
+            .collect()
+    }
+
+    fn stats(&self) -> MotifStats {
+        let n_sites = self.tables.len();
+        let mean = |values: &[f64]| values.iter().sum::<f64>() / values.len() as f64;
+        let mut differences: Vec<f64> = self
+            .methylation_1
+            .iter()
+            .zip(self.methylation_2.iter())
+            .map(|(m_1, m_2)| m_1 - m_2)
+            .collect();
+        differences.sort_by(|a, b| a.total_cmp(b));
+        let median_difference = match n_sites {
+            0 => f64::NAN,
+            n if n % 2 == 0 => 0.5 * (differences[n / 2 - 1] + differences[n / 2]),
+            n => differences[n / 2],
+        };
+        let mantel_haenszel = stats::MantelHaenszel::new(&self.tables);
+        let (breslow_day, breslow_day_df) =
+            stats::breslow_day(&self.tables, mantel_haenszel.odds_ratio);
+        MotifStats {
+            n_sites,
+            mean_methylation_1: mean(&self.methylation_1),
+            mean_methylation_2: mean(&self.methylation_2),
+            mean_difference: mean(&differences),
+            median_difference,
+            strand_correlation: correlation(&self.methylation_1, &self.methylation_2),
+            mantel_haenszel,
+            breslow_day,
+            breslow_day_df,
+        }
+    }
+}
+
+/// Pearson correlation, `NaN` if either variable is constant.
+fn correlation(x: &[f64], y: &[f64]) -> f64 {
+    let n = x.len() as f64;
+    let (mean_x, mean_y) = (x.iter().sum::<f64>() / n, y.iter().sum::<f64>() / n);
+    let (mut covariance, mut variance_x, mut variance_y) = (0.0, 0.0, 0.0);
+    for (x, y) in x.iter().zip(y.iter()) {
+        covariance += (x - mean_x) * (y - mean_y);
+        variance_x += (x - mean_x).powi(2);
+        variance_y += (y - mean_y).powi(2);
+    }
+    if variance_x == 0.0 || variance_y == 0.0 {
+        return f64::NAN;
+    }
+    covariance / (variance_x * variance_y).sqrt()
+}
+
+/// Summarises all site pairs of each motif in a contig, pooling the sites as strata of a
+/// Mantel-Haenszel odds ratio with a Breslow-Day test of its homogeneity.
+///
+/// With a [`Bootstrap`], sites are resampled for percentile intervals of the summary
+/// statistics, using a random stream per contig and motif.
+#[derive(Debug)]
+pub struct MotifSummary {
+    motifs: Vec<MotifSitePairs>,
+    bootstrap: Option<Bootstrap>,
+}
+
+impl MotifSummary {
+    pub fn new(n_motifs: usize, bootstrap: Option<Bootstrap>) -> Self {
+        Self {
+            motifs: vec![MotifSitePairs::default(); n_motifs],
+            bootstrap,
+        }
+    }
+
+    pub fn add(&mut self, motif_idx: usize, record_1: &PileupRecord, record_2: &PileupRecord) {
+        self.motifs[motif_idx].push([
+            record_1.n_mod,
+            record_1.n_valid_cov - record_1.n_mod,
+            record_2.n_mod,
+            record_2.n_valid_cov - record_2.n_mod,
+        ]);
+    }
+
+    /// Writes one row per motif, including motifs without any site pairs.
+    pub fn write(&self, out_path: &str, reference: &str, motif_names: &[String]) -> Result<(), anyhow::Error> {
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(b'\t')
+            .from_path(out_path)?;
+        write_header(&mut writer, self.bootstrap.is_some())?;
+
+        for (sites, motif_name) in self.motifs.iter().zip(motif_names.iter()) {
+            let stats = sites.stats();
+            let interval = stats.mantel_haenszel.confidence_interval();
+            let mut row = vec![
+                reference.to_string(),
+                motif_name.clone(),
+                stats.n_sites.to_string(),
+                stats.mean_methylation_1.to_string(),
+                stats.mean_methylation_2.to_string(),
+                stats.mean_difference.to_string(),
+                stats.median_difference.to_string(),
+                stats.strand_correlation.to_string(),
+                stats.mantel_haenszel.odds_ratio.to_string(),
+                interval.low.to_string(),
+                interval.high.to_string(),
+                stats.breslow_day.to_string(),
+                stats.breslow_day_df.to_string(),
+                stats::chi_squared_sf(stats.breslow_day, stats.breslow_day_df).to_string(),
+            ];
+            if let Some(bootstrap) = &self.bootstrap {
+                let mut rng = SplitMix64::from_key(
+                    bootstrap.seed,
+                    &format!("{}\t{}", reference, motif_name),
+                );
+                for (interval, n_valid) in sites.bootstrap_intervals(bootstrap, &mut rng) {
+                    row.extend([
+                        interval.low.to_string(),
+                        interval.high.to_string(),
+                        n_valid.to_string(),
+                    ]);
+                }
+            }
+            writer.write_record(&row)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+fn write_header(writer: &mut csv::Writer<File>, bootstrap: bool) -> Result<(), anyhow::Error> {
+    let mut header: Vec<String> = [
+        "contig_id",
+        "motif",
+        "n_sites",
+        "mean_methylation_1",
+        "mean_methylation_2",
+        "mean_difference",
+        "median_difference",
+        "strand_correlation",
+        "mh_odds_ratio",
+        "mh_odds_ratio_low",
+        "mh_odds_ratio_high",
+        "breslow_day",
+        "breslow_day_df",
+        "breslow_day_p_value",
+    ]
+    .iter()
+    .map(|name| name.to_string())
+    .collect();
+    if bootstrap {
+        for name in BOOTSTRAPPED {
+            header.extend([
+                format!("{}_boot_low", name),
+                format!("{}_boot_high", name),
+                format!("{}_boot_n", name),
+            ]);
+        }
+    }
+    writer.write_record(&header)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::record;
+
+    #[test]
+    fn test_motif_summary() {
+        let mut summary = MotifSummary::new(2, None);
+        summary.add(0, &record(8), &record(2));
+        summary.add(0, &record(6), &record(4));
+        summary.add(0, &record(9), &record(1));
+
+        let stats = summary.motifs[0].stats();
+        assert_eq!(stats.n_sites, 3);
+        assert!((stats.mean_methylation_1 - 23.0 / 30.0).abs() < 1e-9);
+        assert!((stats.median_difference - 0.6).abs() < 1e-9);
+        assert!((stats.mean_difference - 1.6 / 3.0).abs() < 1e-9);
+        // Methylation of the second site falls as the first rises
+        assert!((stats.strand_correlation + 1.0).abs() < 1e-9);
+        assert!(stats.mantel_haenszel.odds_ratio > 1.0);
+        assert_eq!(stats.breslow_day_df, 2);
+
+        // Motifs without sites are still summarised
+        let stats = summary.motifs[1].stats();
+        assert_eq!(stats.n_sites, 0);
+        assert!(stats.mean_difference.is_nan());
+    }
+
+    #[test]
+    fn test_correlation() {
+        assert!((correlation(&[0.1, 0.2, 0.3], &[0.2, 0.4, 0.6]) - 1.0).abs() < 1e-12);
+        assert!(correlation(&[0.1, 0.2], &[0.5, 0.5]).is_nan());
+    }
+
+    #[test]
+    fn test_bootstrap_intervals() {
+        let mut sites = MotifSitePairs::default();
+        for n_mod in [2, 4, 6, 8, 9] {
+            sites.push([n_mod, 10 - n_mod, 10 - n_mod, n_mod]);
+        }
+        let bootstrap = Bootstrap {
+            replicates: 200,
+            resample_reads: false,
+            seed: 1,
+        };
+        let intervals = sites.bootstrap_intervals(&bootstrap, &mut SplitMix64::new(1));
+        assert_eq!(intervals.len(), BOOTSTRAPPED.len());
+        assert!(intervals.iter().all(|(_, n_valid)| *n_valid == 200));
+        let stats = sites.stats();
+        assert!(intervals[0].0.low <= stats.mean_methylation_1);
+        assert!(stats.mean_methylation_1 <= intervals[0].0.high);
+        // Resampling sites only reuses the observed values
+        assert!(intervals[3].0.low >= -0.6 - 1e-9 && intervals[3].0.high <= 0.8 + 1e-9);
+        // The same seed gives the same intervals
+        let again = sites.bootstrap_intervals(&bootstrap, &mut SplitMix64::new(1));
+        assert_eq!(intervals, again);
+
+        let bootstrap = Bootstrap {
+            resample_reads: true,
+            ..bootstrap
+        };
+        let with_reads = sites.bootstrap_intervals(&bootstrap, &mut SplitMix64::new(1));
+        assert!(with_reads[0].0.high - with_reads[0].0.low > 0.0);
+
+        // Replicates of only the fully methylated site have no odds ratio
+        let mut sites = MotifSitePairs::default();
+        sites.push([10, 0, 10, 0]);
+        sites.push([8, 2, 3, 7]);
+        let intervals = sites.bootstrap_intervals(&bootstrap, &mut SplitMix64::new(1));
+        assert_eq!(intervals[0].1, 200);
+        assert!(intervals[4].1 > 0 && intervals[4].1 < 200);
+    }
+}
@@ -0,0 +1,151 @@
+use crate::{
+    cli,
+    run_statistics::{ContigRows, RunColumns},
+};
+use ahash::{HashMap, HashMapExt};
+use utils::stats;
+
+/// Adjusted p-values of a single row.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct AdjustedPValue {
+    bonferroni: f64,
+    benjamini_hochberg: f64,
+    q_value: f64,
+}
+
+/// Adjusts the p-values of all rows, correcting within each motif, each contig or
+/// across the whole genome.
+fn adjust_p_values(contigs: &[ContigRows], scope: &cli::CorrectionScope) -> Vec<Vec<AdjustedPValue>> {
+    // Rows of each family of tests as (contig index, row index)
+    let mut families: HashMap<usize, Vec<(usize, usize)>> = HashMap::new();
+    for (contig_idx, contig) in contigs.iter().enumerate() {
+        for (row_idx, row) in contig.rows.iter().enumerate() {
+            let family = match scope {
+                cli::CorrectionScope::Motif => row.motif_idx,
+                cli::CorrectionScope::Contig => contig_idx,
+                cli::CorrectionScope::Genome => 0,
+            };
+            families
+                .entry(family)
+                .or_default()
+                .push((contig_idx, row_idx));
+        }
+    }
+
+    let mut adjusted: Vec<Vec<AdjustedPValue>> = contigs
+        .iter()
+        .map(|contig| {
+            vec![
+                AdjustedPValue {
+                    bonferroni: f64::NAN,
+                    benjamini_hochberg: f64::NAN,
+                    q_value: f64::NAN,
+                };
+                contig.rows.len()
+            ]
+        })
+        .collect();
+    for rows in families.values() {
+        let p_values: Vec<f64> = rows
+            .iter()
+            .map(|&(contig_idx, row_idx)| contigs[contig_idx].rows[row_idx].p_value)
+            .collect();
+        let bonferroni = stats::bonferroni(&p_values);
+        let benjamini_hochberg = stats::benjamini_hochberg(&p_values);
+        let q_values = stats::storey_q_values(&p_values);
+        for (i, &(contig_idx, row_idx)) in rows.iter().enumerate() {
+            adjusted[contig_idx][row_idx] = AdjustedPValue {
+                bonferroni: bonferroni[i],
+                benjamini_hochberg: benjamini_hochberg[i],
+                q_value: q_values[i],
+            };
+        }
+    }
+    adjusted
+}
+
+/// Multiple-testing corrected p-values of every row, with Storey q-values if `storey`.
+pub fn adjusted_p_values(
+    contigs: &[ContigRows],
+    scope: &cli::CorrectionScope,
+    storey: bool,
+) -> RunColumns {
+    let mut names = vec!["p_value_bonferroni", "p_value_bh"];
+    if storey {
+        names.push("q_value");
+    }
+    let values = adjust_p_values(contigs, scope)
+        .into_iter()
+        .map(|rows| {
+            rows.into_iter()
+                .map(|adjusted| {
+                    let mut values = vec![
+                        adjusted.bonferroni.to_string(),
+                        adjusted.benjamini_hochberg.to_string(),
+                    ];
+                    if storey {
+                        values.push(adjusted.q_value.to_string());
+                    }
+                    values
+                })
+                .collect()
+        })
+        .collect();
+    RunColumns { names, values }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run_statistics::{PairRow, SiteCounts};
+    use utils::{modtype::ModType, strand::Strand};
+
+    fn contigs() -> Vec<ContigRows> {
+        let row = |motif_idx: usize, p_value: f64| {
+            let counts = SiteCounts {
+                strand: Strand::Positive,
+                mod_type: ModType::SixMA,
+                n_mod: 5,
+                n_valid_cov: 10,
+            };
+            PairRow {
+                motif_idx,
+                p_value,
+                site_1: counts,
+                site_2: counts,
+            }
+        };
+        vec![
+            ContigRows {
+                reference: "contig_1".to_string(),
+                rows: vec![row(0, 0.01), row(1, 0.04)],
+            },
+            ContigRows {
+                reference: "contig_2".to_string(),
+                rows: vec![row(0, 0.02)],
+            },
+        ]
+    }
+
+    #[test]
+    fn test_adjust_p_values() {
+        let contigs = contigs();
+        let genome = adjust_p_values(&contigs, &cli::CorrectionScope::Genome);
+        assert!((genome[0][0].bonferroni - 0.03).abs() < 1e-12);
+        assert!((genome[1][0].benjamini_hochberg - 0.03).abs() < 1e-12);
+        assert!((genome[0][1].benjamini_hochberg - 0.04).abs() < 1e-12);
+
+        let contig = adjust_p_values(&contigs, &cli::CorrectionScope::Contig);
+        assert!((contig[0][0].bonferroni - 0.02).abs() < 1e-12);
+        assert!((contig[1][0].bonferroni - 0.02).abs() < 1e-12);
+
+        let motif = adjust_p_values(&contigs, &cli::CorrectionScope::Motif);
+        assert!((motif[0][0].bonferroni - 0.02).abs() < 1e-12);
+        assert!((motif[0][1].bonferroni - 0.04).abs() < 1e-12);
+        assert!((motif[1][0].bonferroni - 0.04).abs() < 1e-12);
+
+        let columns = adjusted_p_values(&contigs, &cli::CorrectionScope::Genome, true);
+        assert_eq!(columns.names, vec!["p_value_bonferroni", "p_value_bh", "q_value"]);
+        assert_eq!(columns.values[1][0].len(), 3);
+    }
+}
@@ -0,0 +1,239 @@
+use crate::sequence;
+use ahash::{HashMap, HashMapExt};
+use anyhow::Result;
+use log::info;
+use std::fs::File;
+use utils::{
+    coordinate::Position,
+    iupac::IupacBase,
+    modtype::ModType,
+    motif::expand_motif_sequence,
+    scanner::MotifSites,
+    strand::Strand,
+};
+
+/// Parses a motif sequence to sweep, e.g. `CCWGG` or `CCAN{7}GTGG`.
+pub fn parse_sweep_motif(motif: &str) -> Result<Vec<IupacBase>, anyhow::Error> {
+    expand_motif_sequence(motif)?
+        .chars()
+        .map(IupacBase::from_char)
+        .collect()
+}
+
+/// Methylation counts of one motif position pooled over all occurrences.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct PositionCounts {
+    n_sites: u32,
+    n_mod: u32,
+    n_valid_cov: u32,
+}
+
+impl PositionCounts {
+    fn methylation(&self) -> f64 {
+        self.n_mod as f64 / self.n_valid_cov as f64
+    }
+}
+
+/// Mod types with at least one record in the contig, in a fixed order.
+fn contig_mod_types(contig: &sequence::Contig) -> Vec<ModType> {
+    let mut mod_types: Vec<ModType> = Vec::new();
+    for (_, _, mod_type) in contig.records.keys() {
+        if !mod_types.contains(mod_type) {
+            mod_types.push(*mod_type);
+        }
+    }
+    mod_types.sort_by_key(|mod_type| mod_type.to_pileup_code());
+    mod_types
+}
+
+/// Pools the pileup records at every position of every occurrence of `motif`, keyed by
+/// the strand relative to the occurrence and the position in forward motif coordinates.
+fn sweep_counts(
+    contig: &sequence::Contig,
+    motif: &[IupacBase],
+    sites: &MotifSites,
+    mod_types: &[ModType],
+) -> HashMap<(Strand, usize, ModType), PositionCounts> {
+    let mut counts: HashMap<(Strand, usize, ModType), PositionCounts> = HashMap::new();
+    let reverse_motif: Vec<IupacBase> = motif.iter().rev().map(|b| b.complement()).collect();
+    let mut occurrences = vec![(Strand::Positive, &sites.forward)];
+    // Occurrences of palindromic motifs on the negative strand are the same bases again
+    if reverse_motif != motif {
+        occurrences.push((Strand::Negative, &sites.reverse));
+    }
+    let last = motif.len() - 1;
+
+    for (occurrence_strand, starts) in occurrences {
+        for start in starts.iter().map(|&start| Position::new(start)) {
+            for offset in 0..motif.len() {
+                let position = match contig.offset_position(start, offset as isize) {
+                    Some(position) => position,
+                    None => continue,
+                };
+                for strand in [Strand::Positive, Strand::Negative] {
+                    // Negative strand occurrences are read from their own 5' end
+                    let key = match occurrence_strand {
+                        Strand::Positive => (strand, offset),
+                        Strand::Negative => (opposite(strand), last - offset),
+                    };
+                    for mod_type in mod_types {
+                        if let Some(record) = contig.records.get(&(position, strand, *mod_type)) {
+                            let entry = counts.entry((key.0, key.1, *mod_type)).or_default();
+                            entry.n_sites += 1;
+                            entry.n_mod += record.n_mod;
+                            entry.n_valid_cov += record.n_valid_cov;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    counts
+}
+
+fn opposite(strand: Strand) -> Strand {
+    match strand {
+        Strand::Positive => Strand::Negative,
+        Strand::Negative => Strand::Positive,
+    }
+}
+
+/// Position with the highest pooled methylation per strand and mod type.
+fn strongest_positions(
+    counts: &HashMap<(Strand, usize, ModType), PositionCounts>,
+) -> HashMap<(Strand, ModType), usize> {
+    let mut strongest: HashMap<(Strand, ModType), (usize, f64)> = HashMap::new();
+    for (&(strand, position, mod_type), position_counts) in counts.iter() {
+        let methylation = position_counts.methylation();
+        let best = strongest.entry((strand, mod_type)).or_insert((position, methylation));
+        // Ties go to the leftmost position so the result does not depend on map order
+        if methylation > best.1 || (methylation == best.1 && position < best.0) {
+            *best = (position, methylation);
+        }
+    }
+    strongest
+        .into_iter()
+        .map(|(key, (position, _))| (key, position))
+        .collect()
+}
+
+/// Writes the pooled methylation at every position of each motif on both strands, for
+/// every mod type in the contig, marking the strongest position per strand.
+///
+/// Used to confirm the modified position of a motif before specifying pairs for it.
+pub fn motif_position_sweep(
+    contig: &sequence::Contig,
+    motifs: &[Vec<IupacBase>],
+    motif_sites: &[MotifSites],
+    out: &str,
+) -> Result<(), anyhow::Error> {
+    let out_path = format!("{}/{}.sweep.tsv", out, contig.reference);
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(b'\t')
+        .from_path(out_path)?;
+    write_header(&mut writer)?;
+
+    let mod_types = contig_mod_types(contig);
+    for (motif, sites) in motifs.iter().zip(motif_sites.iter()) {
+        let motif_string: String = motif.iter().map(|b| b.to_string()).collect();
+        let counts = sweep_counts(contig, motif, sites, &mod_types);
+        let strongest = strongest_positions(&counts);
+        for mod_type in mod_types.iter() {
+            for strand in [Strand::Positive, Strand::Negative] {
+                if let Some(&position) = strongest.get(&(strand, *mod_type)) {
+                    info!(
+                        "Strongest {} position of {} on the {} strand of {}: {} ({:.3})",
+                        mod_type.to_string(),
+                        motif_string,
+                        strand,
+                        contig.reference,
+                        position,
+                        counts[&(strand, position, *mod_type)].methylation()
+                    );
+                }
+                for (position, base) in motif.iter().enumerate() {
+                    let strand_base = match strand {
+                        Strand::Positive => *base,
+                        Strand::Negative => base.complement(),
+                    };
+                    let position_counts = counts
+                        .get(&(strand, position, *mod_type))
+                        .copied()
+                        .unwrap_or_default();
+                    let is_strongest = strongest.get(&(strand, *mod_type)) == Some(&position);
+                    writer.write_record(&[
+                        contig.reference.clone(),
+                        motif_string.clone(),
+                        mod_type.to_string().to_string(),
+                        strand.to_string(),
+                        position.to_string(),
+                        strand_base.to_string().to_string(),
+                        position_counts.n_sites.to_string(),
+                        position_counts.n_mod.to_string(),
+                        position_counts.n_valid_cov.to_string(),
+                        position_counts.methylation().to_string(),
+                        is_strongest.to_string(),
+                    ])?;
+                }
+            }
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_header(writer: &mut csv::Writer<File>) -> Result<(), anyhow::Error> {
+    writer.write_record([
+        "contig_id",
+        "motif_sequence",
+        "mod_type",
+        "strand",
+        "motif_position",
+        "base",
+        "n_sites",
+        "n_mod",
+        "n_valid_cov",
+        "methylation",
+        "strongest",
+    ])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::record_at;
+    use utils::scanner::MotifScanner;
+
+    #[test]
+    fn test_sweep_counts() {
+        // GATCA on the positive strand at 1 and on the negative strand at 8 (TGATC)
+        let mut contig = sequence::Contig::new("test", "AGATCAATTGATCT");
+        contig.add_record(record_at(2, Strand::Positive, ModType::SixMA, 8));
+        contig.add_record(record_at(5, Strand::Positive, ModType::SixMA, 1));
+        contig.add_record(record_at(11, Strand::Negative, ModType::SixMA, 6));
+        contig.add_record(record_at(3, Strand::Negative, ModType::SixMA, 3));
+        contig.add_record(record_at(4, Strand::Positive, ModType::FiveMC, 2));
+
+        let motif = parse_sweep_motif("GATCA").unwrap();
+        let scanner = MotifScanner::from_sequences([motif.as_slice()]).unwrap();
+        let sites = contig.scan(&scanner);
+        assert_eq!(sites[0].forward, vec![1]);
+        assert_eq!(sites[0].reverse, vec![8]);
+
+        let mod_types = contig_mod_types(&contig);
+        assert_eq!(mod_types, vec![ModType::SixMA, ModType::FiveMC]);
+        let counts = sweep_counts(&contig, &motif, &sites[0], &mod_types);
+        // The A of both occurrences
+        let a = counts[&(Strand::Positive, 1, ModType::SixMA)];
+        assert_eq!((a.n_sites, a.n_mod, a.n_valid_cov), (2, 14, 20));
+        assert_eq!(counts[&(Strand::Positive, 4, ModType::SixMA)].n_mod, 1);
+        assert_eq!(counts[&(Strand::Negative, 2, ModType::SixMA)].n_mod, 3);
+        assert_eq!(counts[&(Strand::Positive, 3, ModType::FiveMC)].n_mod, 2);
+        assert_eq!(counts.len(), 4);
+
+        let strongest = strongest_positions(&counts);
+        assert_eq!(strongest[&(Strand::Positive, ModType::SixMA)], 1);
+        assert_eq!(strongest[&(Strand::Negative, ModType::SixMA)], 2);
+    }
+}
@@ -0,0 +1,170 @@
+use crate::{cli, duplex_states, empirical_bayes, methylation_state, multiple_testing};
+use anyhow::{bail, Result};
+use log::info;
+use std::fs;
+use utils::{modtype::ModType, pileup::PileupRecord, strand::Strand};
+
+/// Column after which the run statistics are inserted.
+const INSERT_AFTER_COLUMN: &str = "p_value_less";
+
+/// Read counts of one site of a motif pair row.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SiteCounts {
+    pub strand: Strand,
+    pub mod_type: ModType,
+    pub n_mod: u32,
+    pub n_valid_cov: u32,
+}
+
+impl From<&PileupRecord> for SiteCounts {
+    fn from(record: &PileupRecord) -> Self {
+        Self {
+            strand: record.strand,
+            mod_type: record.mod_type,
+            n_mod: record.n_mod,
+            n_valid_cov: record.n_valid_cov,
+        }
+    }
+}
+
+/// What is kept of a written motif pair row for statistics over all contigs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PairRow {
+    pub motif_idx: usize,
+    pub p_value: f64,
+    pub site_1: SiteCounts,
+    pub site_2: SiteCounts,
+}
+
+/// Motif pair rows of a contig, in the order they were written.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ContigRows {
+    pub reference: String,
+    pub rows: Vec<PairRow>,
+}
+
+/// Columns to add to the motif pair rows, with the values indexed by contig and row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunColumns {
+    pub names: Vec<&'static str>,
+    pub values: Vec<Vec<Vec<String>>>,
+}
+
+/// Rewrites a motif pair file with `values` inserted as columns after the raw p-values.
+fn insert_columns(path: &str, names: &[&str], values: &[Vec<String>]) -> Result<(), anyhow::Error> {
+    let mut reader = csv::ReaderBuilder::new().delimiter(b'\t').from_path(path)?;
+    let header = reader.headers()?.clone();
+    let column = match header.iter().position(|name| name == INSERT_AFTER_COLUMN) {
+        Some(i) => i + 1,
+        None => bail!("Column {} not found in {}", INSERT_AFTER_COLUMN, path),
+    };
+    let tmp_path = format!("{}.tmp", path);
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(b'\t')
+        .from_path(&tmp_path)?;
+
+    let mut new_header: Vec<&str> = header.iter().collect();
+    new_header.splice(column..column, names.iter().copied());
+    writer.write_record(&new_header)?;
+
+    let mut n_rows = 0;
+    for record in reader.records() {
+        let record = record?;
+        let row_values = match values.get(n_rows) {
+            Some(row_values) => row_values,
+            None => bail!("More rows in {} than expected", path),
+        };
+        let mut row: Vec<String> = record.iter().map(|field| field.to_string()).collect();
+        row.splice(column..column, row_values.iter().cloned());
+        writer.write_record(&row)?;
+        n_rows += 1;
+    }
+    if n_rows != values.len() {
+        bail!("Expected {} rows in {}, found {}", values.len(), path, n_rows);
+    }
+    writer.flush()?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+/// Adds the statistics that need the rows of all contigs to the motif pair files:
+/// multiple-testing corrected p-values, empirical Bayes estimates and methylation states.
+/// Also writes the priors, state counts and duplex state fractions of the run.
+///
+/// Rows are streamed to the files while the contigs are processed, so this is a second
+/// pass over the written files.
+pub fn write_run_statistics(
+    contigs: &[ContigRows],
+    motif_names: &[String],
+    args: &cli::Cli,
+) -> Result<(), anyhow::Error> {
+    let priors = empirical_bayes::fit_priors(contigs);
+    empirical_bayes::write_priors(
+        &format!("{}/beta_binomial_priors.tsv", args.out),
+        &priors,
+        motif_names,
+    )?;
+    let states = methylation_state::classify_rows(
+        contigs,
+        &priors,
+        &methylation_state::StateThresholds::from(args),
+    );
+    methylation_state::write_state_counts(
+        &format!("{}/methylation_states.tsv", args.out),
+        contigs,
+        &states,
+        motif_names,
+    )?;
+    duplex_states::write_duplex_states(
+        &format!("{}/duplex_states.tsv", args.out),
+        contigs,
+        motif_names,
+    )?;
+    let column_sets = [
+        multiple_testing::adjusted_p_values(contigs, &args.correction_scope, args.storey),
+        empirical_bayes::posterior_columns(contigs, &priors, args.methylation_threshold),
+        methylation_state::state_columns(contigs, &states),
+    ];
+
+    let names: Vec<&str> = column_sets.iter().flat_map(|set| set.names.clone()).collect();
+    for (contig_idx, contig) in contigs.iter().enumerate() {
+        let values: Vec<Vec<String>> = (0..contig.rows.len())
+            .map(|row_idx| {
+                column_sets
+                    .iter()
+                    .flat_map(|set| set.values[contig_idx][row_idx].clone())
+                    .collect()
+            })
+            .collect();
+        insert_columns(&format!("{}/{}.tsv", args.out, contig.reference), &names, &values)?;
+    }
+    info!(
+        "Added run statistics to {} motif pair rows",
+        contigs.iter().map(|contig| contig.rows.len()).sum::<usize>()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_insert_columns() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        write!(file, "p_value\tp_value_less\tmatched_sequence\n0.01\t0.005\tGATC\n").unwrap();
+        let path = file.path().to_str().unwrap().to_string();
+        let values = vec![vec!["0.02".to_string(), "0.015".to_string()]];
+        insert_columns(&path, &["p_value_bonferroni", "p_value_bh"], &values).unwrap();
+        let written = fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            written,
+            "p_value\tp_value_less\tp_value_bonferroni\tp_value_bh\tmatched_sequence\n\
+             0.01\t0.005\t0.02\t0.015\tGATC\n"
+        );
+
+        // Row counts must match the values
+        assert!(insert_columns(&path, &["q_value"], &[]).is_err());
+    }
+}
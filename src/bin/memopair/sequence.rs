@@ -1,28 +1,91 @@
-use crate::motif::{Motif, MotifLike};
 use crate::pileup::{PileupChunk, PileupRecord};
 use ahash::{HashMap, HashMapExt};
+use std::ops::Range;
+use utils::coordinate::Position;
 use utils::modtype::ModType;
+use utils::packed_sequence::PackedSequence;
+use utils::scanner::{MotifScanner, MotifSites};
 use utils::strand::Strand;
-use regex::Regex;
 
 #[derive(Debug, Clone)]
 pub struct Contig {
     pub reference: String,
-    pub sequence: String,
-    pub records: HashMap<(usize, Strand, ModType), PileupRecord>,
+    pub sequence: PackedSequence,
+    pub circular: bool,
+    pub records: HashMap<(Position, Strand, ModType), PileupRecord>,
 }
 
 impl Contig {
+    #[cfg(test)]
     pub fn new(reference: &str, sequence: &str) -> Self {
+        Self::from_packed(reference, PackedSequence::from_bytes(sequence.as_bytes()))
+    }
+
+    pub fn from_packed(reference: &str, sequence: PackedSequence) -> Self {
         Self {
             reference: reference.to_string(),
-            sequence: sequence.to_string(),
+            sequence,
+            circular: false,
             records: HashMap::new(),
         }
     }
 
+    pub fn with_circular(mut self, circular: bool) -> Self {
+        self.circular = circular;
+        self
+    }
+
+    /// Shifts `position` by `offset` bases, wrapping around the origin on circular contigs.
+    ///
+    /// Returns `None` if the shifted position falls outside a linear contig.
+    pub fn offset_position(&self, position: Position, offset: isize) -> Option<Position> {
+        position.shifted(offset, self.sequence.len(), self.circular)
+    }
+
+    /// The bases of the motif occurrence of length `len` starting at `start`, read on `strand`.
+    ///
+    /// Wraps around the origin like [`Contig::offset_position`]. On the negative strand the
+    /// sequence is reverse complemented, so it reads like the motif that was matched.
+    pub fn occurrence_sequence(&self, start: Position, len: usize, strand: Strand) -> String {
+        self.region_sequence(start, 0..len as isize, strand)
+    }
+
+    /// Up to `k` bases upstream and downstream of a motif occurrence, read on `strand`.
+    ///
+    /// Flanks are cut short at the ends of linear contigs.
+    pub fn flanking_sequences(
+        &self,
+        start: Position,
+        len: usize,
+        strand: Strand,
+        k: usize,
+    ) -> (String, String) {
+        let (len, k) = (len as isize, k as isize);
+        let left = self.region_sequence(start, -k..0, strand);
+        let right = self.region_sequence(start, len..len + k, strand);
+        match strand {
+            Strand::Positive => (left, right),
+            Strand::Negative => (right, left),
+        }
+    }
+
+    fn region_sequence(&self, start: Position, offsets: Range<isize>, strand: Strand) -> String {
+        let bases = offsets
+            .filter_map(|offset| self.offset_position(start, offset))
+            .filter_map(|position| self.sequence.get(position.get()));
+        match strand {
+            Strand::Positive => bases.map(|base| base.to_string()).collect(),
+            Strand::Negative => bases
+                .collect::<Vec<_>>()
+                .into_iter()
+                .rev()
+                .map(|base| base.complement().to_string())
+                .collect(),
+        }
+    }
+
     pub fn add_record(&mut self, record: PileupRecord) {
-        let key = (record.position, record.strand, record.mod_type);
+        let key = (Position::new(record.position), record.strand, record.mod_type);
         self.records.insert(key, record);
     }
 
@@ -39,43 +102,49 @@ impl Contig {
         }
     }
 
-    pub fn find_motif_indeces(&self, motif: &Motif) -> Option<Vec<usize>> {
-        let mut indices = Vec::new();
-        let motif_regex = motif.regex().unwrap();
-        let re = Regex::new(&motif_regex).unwrap();
-        // Find matches in the contig sequence of the motif
-        re.find_iter(&self.sequence)
-            .map(|m| indices.push(m.start() as usize + motif.position as usize))
-            .for_each(drop);
-        if indices.is_empty() {
-            return None;
-        }
-        Some(indices)
-    }
-
-    pub fn find_complement_motif_indeces(&self, motif: &Motif) -> Option<Vec<usize>> {
-        let mut indices = Vec::new();
-        let complement_motif = motif.reverse_complement().unwrap();
-        let motif_regex = complement_motif.regex().unwrap();
-        let re = Regex::new(&motif_regex).unwrap();
-        re.find_iter(&self.sequence)
-            .map(|m| indices.push(m.start() as usize + complement_motif.position as usize))
-            .for_each(drop);
-        if indices.is_empty() {
-            return None;
+    /// Finds the sites of all motifs of `scanner`, respecting the contig topology.
+    pub fn scan(&self, scanner: &MotifScanner) -> Vec<MotifSites> {
+        if self.circular {
+            scanner.scan_packed_circular(&self.sequence)
+        } else {
+            scanner.scan_packed(&self.sequence)
         }
-        Some(indices)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::motif::Motif;
+    use utils::motif::{Motif, MotifLike};
     use crate::pileup::PileupRecord;
+    use anyhow::Result;
+    use regex::Regex;
     use utils::modtype::ModType;
     use utils::strand::Strand;
 
+    impl Contig {
+        fn find_motif_indeces(&self, motif: &Motif) -> Result<Option<Vec<usize>>> {
+            let indices = self.scan_motif(motif)?.forward;
+            if indices.is_empty() {
+                return Ok(None);
+            }
+            Ok(Some(indices))
+        }
+
+        fn find_complement_motif_indeces(&self, motif: &Motif) -> Result<Option<Vec<usize>>> {
+            let indices = self.scan_motif(motif)?.reverse;
+            if indices.is_empty() {
+                return Ok(None);
+            }
+            Ok(Some(indices))
+        }
+
+        fn scan_motif(&self, motif: &Motif) -> Result<MotifSites> {
+            let scanner = MotifScanner::new([motif])?;
+            Ok(self.scan(&scanner).pop().unwrap_or_default())
+        }
+    }
+
     #[test]
     fn test_contig_add_record() {
         let mut contig = Contig::new("test", "ACGT");
@@ -92,7 +161,7 @@ mod tests {
         contig.add_record(record.clone());
         assert_eq!(contig.records.len(), 1);
         assert_eq!(
-            contig.records.get(&(0, Strand::Positive, ModType::SixMA)),
+            contig.records.get(&(Position::new(0), Strand::Positive, ModType::SixMA)),
             Some(&record)
         );
     }
@@ -128,11 +197,11 @@ mod tests {
         contig.add_records(records.clone());
         assert_eq!(contig.records.len(), 2);
         assert_eq!(
-            contig.records.get(&(0, Strand::Positive, ModType::SixMA)),
+            contig.records.get(&(Position::new(0), Strand::Positive, ModType::SixMA)),
             Some(&records.records[0])
         );
         assert_eq!(
-            contig.records.get(&(1, Strand::Positive, ModType::SixMA)),
+            contig.records.get(&(Position::new(1), Strand::Positive, ModType::SixMA)),
             Some(&records.records[1])
         );
     }
@@ -141,7 +210,7 @@ mod tests {
     fn test_contig_find_motif_indeces() {
         let contig = Contig::new("test", "ACGTACGTACGTACGT");
         let motif = Motif::new("ACGT", "6mA", 0).unwrap();
-        let indeces = contig.find_motif_indeces(&motif).unwrap();
+        let indeces = contig.find_motif_indeces(&motif).unwrap().unwrap();
         assert_eq!(indeces, vec![0, 4, 8, 12]);
 
         let contig = Contig::new(
@@ -149,7 +218,7 @@ mod tests {
             "ACCCCGGAGGTCGTACGCCGGATCCGGTACCGGACGTACCGGTCGCCGGAT",
         );
         let motif = Motif::new("CCGGA", "6mA", 4).unwrap();
-        let indeces = contig.find_motif_indeces(&motif);
+        let indeces = contig.find_motif_indeces(&motif).unwrap();
         assert_eq!(indeces, Some(vec![7, 21, 33, 49]));
     }
 
@@ -157,7 +226,7 @@ mod tests {
     fn test_contig_find_complement_motif_indeces() {
         let contig = Contig::new("test", "ACGTACGTACGTACGT");
         let motif = Motif::new("ACGT", "6mA", 0).unwrap();
-        let indeces = contig.find_complement_motif_indeces(&motif);
+        let indeces = contig.find_complement_motif_indeces(&motif).unwrap();
         assert_eq!(indeces, Some(vec![3, 7, 11, 15]));
 
         let contig = Contig::new(
@@ -165,22 +234,144 @@ mod tests {
             "ACCTCCGGCCGGAGGTCGTACGCCGGATCCGGTCCGGTCCGGTACCGGACGTACCGGTCGCCGGAT",
         );
         let motif = Motif::new("CCGGA", "6mA", 4).unwrap();
-        let indeces = contig.find_complement_motif_indeces(&motif);
+        let indeces = contig.find_complement_motif_indeces(&motif).unwrap();
         assert_eq!(indeces, Some(vec![3, 27, 32, 37]));
 
         let contig = Contig::new("test", "CCTCCTCCTCCTCCTCC");
         let motif = Motif::new("CCTCC", "5mC", 0).unwrap();
-        let indeces = contig.find_complement_motif_indeces(&motif);
+        let indeces = contig.find_complement_motif_indeces(&motif).unwrap();
         assert_eq!(indeces, None);
 
         let contig = Contig::new("test", "GGAGGAGGAGGAGGAGG");
         let motif = Motif::new("CCTCC", "5mC", 0).unwrap();
-        let indeces = contig.find_complement_motif_indeces(&motif);
-        assert_eq!(indeces, Some(vec![4, 10, 16])); // only count full matches
+        let indeces = contig.find_complement_motif_indeces(&motif).unwrap();
+        assert_eq!(indeces, Some(vec![4, 7, 10, 13, 16])); // overlapping matches are reported
 
         let contig = Contig::new("test", "GGAGCAGCTGGAGGAGGACAGCTGGGAGG");
         let motif = Motif::new("CAGCTG", "4mC", 3).unwrap();
-        let indeces = contig.find_complement_motif_indeces(&motif);
+        let indeces = contig.find_complement_motif_indeces(&motif).unwrap();
         assert_eq!(indeces, Some(vec![6, 20])); // only count full matches
     }
+
+    /// Naive scan testing every start position, used as ground truth for the motif finders.
+    fn brute_force_indeces(sequence: &str, regex: &str, position: u8) -> Option<Vec<usize>> {
+        let re = Regex::new(&format!("^{}", regex)).unwrap();
+        let indices: Vec<usize> = (0..sequence.len())
+            .filter(|&i| re.is_match(&sequence[i..]))
+            .map(|i| i + position as usize)
+            .collect();
+        if indices.is_empty() {
+            return None;
+        }
+        Some(indices)
+    }
+
+    #[test]
+    fn test_contig_find_overlapping_motif_indeces() {
+        // GCNGC overlaps itself through the shared GC prefix and suffix
+        let contig = Contig::new("test", "GCAGCTGCGGC");
+        let motif = Motif::new("GCNGC", "5mC", 1).unwrap();
+        let indeces = contig.find_motif_indeces(&motif).unwrap();
+        assert_eq!(indeces, Some(vec![1, 4, 7]));
+
+        let contig = Contig::new("test", "AAAAAA");
+        let motif = Motif::new("AAAA", "6mA", 3).unwrap();
+        let indeces = contig.find_motif_indeces(&motif).unwrap();
+        assert_eq!(indeces, Some(vec![3, 4, 5]));
+
+        // Suffix equals prefix
+        let contig = Contig::new("test", "ATATATAT");
+        let motif = Motif::new("ATAT", "6mA", 0).unwrap();
+        let indeces = contig.find_motif_indeces(&motif).unwrap();
+        assert_eq!(indeces, Some(vec![0, 2, 4]));
+        let indeces = contig.find_complement_motif_indeces(&motif).unwrap();
+        assert_eq!(indeces, Some(vec![3, 5, 7]));
+    }
+
+    #[test]
+    fn test_contig_find_motif_indeces_matches_brute_force() {
+        let sequences = [
+            "GAGTCGAGTCGAATCGATCGATCGATC",
+            "CCAGGCCTGGCCAGGCCCCTGGGG",
+            "GGAGGAGGAGGAGGAGG",
+            "AAAAAAAAAAAAAAAAAAAA",
+            "ATATATATATATATAGATCGATCTAGATCTA",
+            "GGAGCAGCTGGAGGAGGACAGCTGGGAGG",
+            "GCAGCTGCGGCGCNGCGC",
+        ];
+        let motifs = [
+            Motif::new("GANTC", "6mA", 1).unwrap(),
+            Motif::new("GATC", "6mA", 1).unwrap(),
+            Motif::new("CCWGG", "5mC", 1).unwrap(),
+            Motif::new("GGAGG", "6mA", 2).unwrap(),
+            Motif::new("GCNGC", "5mC", 1).unwrap(),
+            Motif::new("AAAA", "6mA", 3).unwrap(),
+            Motif::new("ATAT", "6mA", 2).unwrap(),
+            Motif::new("CAGCTG", "4mC", 3).unwrap(),
+            Motif::new("RGATCY", "6mA", 2).unwrap(),
+        ];
+        for sequence in sequences {
+            let contig = Contig::new("test", sequence);
+            for motif in motifs.iter() {
+                let expected = brute_force_indeces(sequence, &motif.regex().unwrap(), motif.position);
+                assert_eq!(contig.find_motif_indeces(motif).unwrap(), expected);
+
+                let complement = motif.reverse_complement().unwrap();
+                let expected =
+                    brute_force_indeces(sequence, &complement.regex().unwrap(), complement.position);
+                assert_eq!(contig.find_complement_motif_indeces(motif).unwrap(), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_contig_offset_position() {
+        let contig = Contig::new("test", "ACGTACGTAC");
+        assert_eq!(contig.offset_position(Position::new(2), 3), Some(Position::new(5)));
+        assert_eq!(contig.offset_position(Position::new(2), -3), None);
+        assert_eq!(contig.offset_position(Position::new(8), 2), None);
+
+        let contig = contig.with_circular(true);
+        assert_eq!(contig.offset_position(Position::new(2), 3), Some(Position::new(5)));
+        assert_eq!(contig.offset_position(Position::new(2), -3), Some(Position::new(9)));
+        assert_eq!(contig.offset_position(Position::new(8), 2), Some(Position::new(0)));
+    }
+
+    #[test]
+    fn test_contig_occurrence_sequence() {
+        let contig = Contig::new("test", "GGCCTGGAAC");
+        assert_eq!(contig.occurrence_sequence(Position::new(2), 5, Strand::Positive), "CCTGG");
+        assert_eq!(contig.occurrence_sequence(Position::new(2), 5, Strand::Negative), "CCAGG");
+
+        let contig = contig.with_circular(true);
+        assert_eq!(contig.occurrence_sequence(Position::new(8), 4, Strand::Positive), "ACGG");
+        assert_eq!(contig.occurrence_sequence(Position::new(8), 4, Strand::Negative), "CCGT");
+    }
+
+    #[test]
+    fn test_contig_flanking_sequences() {
+        let contig = Contig::new("test", "GGCCTGGAAC");
+        let (upstream, downstream) = contig.flanking_sequences(Position::new(2), 5, Strand::Positive, 2);
+        assert_eq!((upstream.as_str(), downstream.as_str()), ("GG", "AA"));
+        let (upstream, downstream) = contig.flanking_sequences(Position::new(2), 5, Strand::Negative, 2);
+        assert_eq!((upstream.as_str(), downstream.as_str()), ("TT", "CC"));
+
+        // Cut short at the contig ends unless circular
+        let (upstream, downstream) = contig.flanking_sequences(Position::new(1), 5, Strand::Positive, 3);
+        assert_eq!((upstream.as_str(), downstream.as_str()), ("G", "GAA"));
+        let contig = contig.with_circular(true);
+        let (upstream, _) = contig.flanking_sequences(Position::new(1), 5, Strand::Positive, 3);
+        assert_eq!(upstream, "ACG");
+    }
+
+    #[test]
+    fn test_contig_find_circular_motif_indeces() {
+        let contig = Contig::new("test", "TCAAAAGATCAAAAGA");
+        let motif = Motif::new("GATC", "6mA", 1).unwrap();
+        assert_eq!(contig.find_motif_indeces(&motif).unwrap(), Some(vec![7]));
+
+        let contig = contig.with_circular(true);
+        assert_eq!(contig.find_motif_indeces(&motif).unwrap(), Some(vec![7, 15]));
+        assert_eq!(contig.find_complement_motif_indeces(&motif).unwrap(), Some(vec![8, 0]));
+    }
 }
@@ -0,0 +1,334 @@
+use crate::sequence;
+use ahash::{HashMap, HashMapExt};
+use anyhow::Result;
+use log::debug;
+use std::fs::File;
+use utils::{
+    coordinate::{CoordinateSystem, Interval, Position},
+    pileup::PileupRecord,
+    scanner::MotifSites,
+    site_set::{MethylationPattern, ModPosition, MotifSiteSet},
+    strand::Strand,
+};
+
+/// Writes the counts of every modified position per occurrence of each site set, and
+/// a summary of how often each methylation pattern occurs.
+///
+/// `motif_sites` holds occurrence starts as found by `MotifScanner::from_sequences`.
+/// Occurrences are only reported if all modified positions are covered in the pileup.
+pub fn site_set_methylation_pattern(
+    contig: &sequence::Contig,
+    site_sets: &[MotifSiteSet],
+    motif_sites: &[MotifSites],
+    methylation_threshold: f64,
+    coordinates: CoordinateSystem,
+    out: &str,
+) -> Result<(), anyhow::Error> {
+    let out_path = format!("{}/{}.site_sets.tsv", out, contig.reference);
+    let mut record_writer =
+        SiteSetRecordWriter::new(&out_path, coordinates, contig.sequence.len())?;
+    record_writer.write_header()?;
+    let out_path = format!("{}/{}.site_set_patterns.tsv", out, contig.reference);
+    let mut pattern_writer = PatternSummaryWriter::new(&out_path)?;
+    pattern_writer.write_header()?;
+
+    for (site_set, sites) in site_sets.iter().zip(motif_sites.iter()) {
+        debug!("Processing site set: {}", site_set);
+        let mut pattern_counts: HashMap<MethylationPattern, u32> = HashMap::new();
+        let reverse_positions = site_set.reverse_positions();
+        let mut occurrences = vec![(Strand::Positive, &sites.forward, &site_set.positions)];
+        if !site_set.is_palindromic() {
+            occurrences.push((Strand::Negative, &sites.reverse, &reverse_positions));
+        }
+
+        for (strand, starts, positions) in occurrences {
+            for start in starts.iter().map(|&start| Position::new(start)) {
+                let records = match occurrence_records(contig, start, positions) {
+                    Some(records) => records,
+                    None => continue,
+                };
+                let pattern = MethylationPattern(
+                    records
+                        .iter()
+                        .map(|record| {
+                            record.n_mod as f64 / record.n_valid_cov as f64 >= methylation_threshold
+                        })
+                        .collect(),
+                );
+                // Positions are always reported in the order of the site set
+                for ((mod_position, record), methylated) in site_set
+                    .positions
+                    .iter()
+                    .zip(records.iter())
+                    .zip(pattern.0.iter())
+                {
+                    record_writer.write_record(
+                        site_set,
+                        &Interval::new(start, site_set.sequence.len()),
+                        strand,
+                        &pattern,
+                        mod_position,
+                        record,
+                        *methylated,
+                    )?;
+                }
+                *pattern_counts.entry(pattern).or_default() += 1;
+            }
+        }
+
+        let n_occurrences: u32 = pattern_counts.values().sum();
+        let mut patterns: Vec<(MethylationPattern, u32)> = pattern_counts.into_iter().collect();
+        patterns.sort_by(|a, b| b.1.cmp(&a.1).then(b.0.cmp(&a.0)));
+        for (pattern, count) in patterns {
+            pattern_writer.write_record(
+                &contig.reference,
+                site_set,
+                &pattern,
+                count,
+                n_occurrences,
+            )?;
+        }
+    }
+    record_writer.flush()?;
+    pattern_writer.flush()?;
+    Ok(())
+}
+
+/// Pileup records of the modified positions of an occurrence starting at `start`.
+fn occurrence_records<'a>(
+    contig: &'a sequence::Contig,
+    start: Position,
+    positions: &[ModPosition],
+) -> Option<Vec<&'a PileupRecord>> {
+    positions
+        .iter()
+        .map(|mod_position| {
+            let position = contig.offset_position(start, mod_position.position as isize)?;
+            contig
+                .records
+                .get(&(position, mod_position.strand, mod_position.mod_type))
+        })
+        .collect()
+}
+
+#[derive(Debug)]
+struct SiteSetRecordWriter {
+    csv_writer: csv::Writer<File>,
+    coordinates: CoordinateSystem,
+    contig_len: usize,
+}
+
+impl SiteSetRecordWriter {
+    pub fn new(
+        out_path: &str,
+        coordinates: CoordinateSystem,
+        contig_len: usize,
+    ) -> Result<Self, anyhow::Error> {
+        let csv_writer = csv::WriterBuilder::new()
+            .delimiter(b'\t')
+            .from_path(out_path)?;
+        Ok(Self {
+            csv_writer,
+            coordinates,
+            contig_len,
+        })
+    }
+
+    pub fn write_header(&mut self) -> Result<(), anyhow::Error> {
+        self.csv_writer.write_record([
+            "contig_id",
+            "motif_start_position",
+            "strand",
+            "site_set",
+            "pattern",
+            "mod_position",
+            "mod_type",
+            "mod_strand",
+            "position",
+            "n_mod",
+            "n_nomod",
+            "n_diff",
+            "methylation",
+            "methylated",
+            "motif_end_position",
+        ])?;
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn write_record(
+        &mut self,
+        site_set: &MotifSiteSet,
+        occurrence: &Interval,
+        strand: Strand,
+        pattern: &MethylationPattern,
+        mod_position: &ModPosition,
+        record: &PileupRecord,
+        methylated: bool,
+    ) -> Result<(), anyhow::Error> {
+        let n_nomod = record.n_valid_cov - record.n_mod;
+        let methylation = record.n_mod as f64 / record.n_valid_cov as f64;
+        let (start_position, end_position) =
+            self.coordinates.interval(occurrence, self.contig_len);
+        self.csv_writer.write_record(&[
+            record.reference.clone(),
+            start_position.to_string(),
+            strand.to_string(),
+            site_set.to_string(),
+            pattern.to_string(),
+            mod_position.to_string(),
+            record.mod_type.to_string().to_string(),
+            record.strand.to_string(),
+            self.coordinates
+                .position(Position::new(record.position))
+                .to_string(),
+            record.n_mod.to_string(),
+            n_nomod.to_string(),
+            record.n_diff.to_string(),
+            methylation.to_string(),
+            methylated.to_string(),
+            end_position.to_string(),
+        ])?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), anyhow::Error> {
+        self.csv_writer.flush()?;
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct PatternSummaryWriter {
+    csv_writer: csv::Writer<File>,
+}
+
+impl PatternSummaryWriter {
+    pub fn new(out_path: &str) -> Result<Self, anyhow::Error> {
+        let csv_writer = csv::WriterBuilder::new()
+            .delimiter(b'\t')
+            .from_path(out_path)?;
+        Ok(Self { csv_writer })
+    }
+
+    pub fn write_header(&mut self) -> Result<(), anyhow::Error> {
+        self.csv_writer.write_record([
+            "contig_id",
+            "site_set",
+            "pattern",
+            "n_occurrences",
+            "fraction",
+        ])?;
+        Ok(())
+    }
+
+    pub fn write_record(
+        &mut self,
+        reference: &str,
+        site_set: &MotifSiteSet,
+        pattern: &MethylationPattern,
+        count: u32,
+        total: u32,
+    ) -> Result<(), anyhow::Error> {
+        self.csv_writer.write_record(&[
+            reference.to_string(),
+            site_set.to_string(),
+            pattern.to_string(),
+            count.to_string(),
+            (count as f64 / total as f64).to_string(),
+        ])?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), anyhow::Error> {
+        self.csv_writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::record_at;
+    use utils::{modtype::ModType, scanner::MotifScanner};
+
+    fn record(position: usize, strand: Strand, n_mod: u32) -> PileupRecord {
+        record_at(position, strand, ModType::SixMA, n_mod)
+    }
+
+    #[test]
+    fn test_occurrence_records() {
+        let mut contig = sequence::Contig::new("test", "TTGATCATT");
+        let site_set: MotifSiteSet = "GATCA_a+1_a-2_a+4".parse().unwrap();
+        contig.add_record(record(3, Strand::Positive, 10));
+        contig.add_record(record(4, Strand::Negative, 0));
+        contig.add_record(record(6, Strand::Positive, 5));
+
+        let scanner = MotifScanner::from_sequences([site_set.sequence.as_slice()]).unwrap();
+        let sites = contig.scan(&scanner);
+        assert_eq!(sites[0].forward, vec![2]);
+        // TGATC on the negative strand
+        assert_eq!(sites[0].reverse, vec![1]);
+
+        let records = occurrence_records(&contig, Position::new(2), &site_set.positions).unwrap();
+        assert_eq!(
+            records.iter().map(|r| r.n_mod).collect::<Vec<u32>>(),
+            vec![10, 0, 5]
+        );
+        // Negative strand occurrence lacks coverage at its first modified base
+        assert!(occurrence_records(&contig, Position::new(1), &site_set.reverse_positions()).is_none());
+    }
+
+    #[test]
+    fn test_site_set_methylation_pattern() {
+        // GATC at 0, 6, 12 and 18, each also found as its own reverse complement
+        let sequence = format!("{}GATC", "GATCAA".repeat(3));
+        let mut contig = sequence::Contig::new("test", &sequence);
+        let site_set: MotifSiteSet = "GATC_a+1_a-2".parse().unwrap();
+        for (start, n_mod_1, n_mod_2) in [(0, 9, 1), (6, 8, 7), (12, 10, 0), (18, 2, 6)] {
+            contig.add_record(record(start + 1, Strand::Positive, n_mod_1));
+            contig.add_record(record(start + 2, Strand::Negative, n_mod_2));
+        }
+        let scanner = MotifScanner::from_sequences([site_set.sequence.as_slice()]).unwrap();
+        let sites = contig.scan(&scanner);
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().to_str().unwrap();
+        site_set_methylation_pattern(
+            &contig,
+            &[site_set],
+            &sites,
+            0.5,
+            CoordinateSystem::Bed,
+            out,
+        )
+        .unwrap();
+
+        let read = |name: &str| -> Vec<csv::StringRecord> {
+            csv::ReaderBuilder::new()
+                .delimiter(b'\t')
+                .from_path(format!("{}/test.{}", out, name))
+                .unwrap()
+                .records()
+                .map(|r| r.unwrap())
+                .collect()
+        };
+        // The palindromic site set is reported once per occurrence, two rows each
+        let records = read("site_sets.tsv");
+        assert_eq!(records.len(), 8);
+        assert_eq!(&records[0][1], "0");
+        assert_eq!(&records[0][4], "10");
+        assert_eq!(&records[0][5], "a+1");
+        assert_eq!(&records[1][5], "a-2");
+        assert_eq!(&records[1][8], "2");
+        assert_eq!(&records[1][13], "false");
+        assert_eq!(&records[1][14], "4");
+
+        // Sorted by count, then by pattern with methylated positions first
+        let patterns = read("site_set_patterns.tsv");
+        let rows: Vec<(&str, &str, &str)> = patterns.iter().map(|r| (&r[2], &r[3], &r[4])).collect();
+        assert_eq!(
+            rows,
+            vec![("10", "2", "0.5"), ("11", "1", "0.25"), ("01", "1", "0.25")]
+        );
+    }
+}
@@ -0,0 +1,20 @@
+use utils::{modtype::ModType, pileup::PileupRecord, strand::Strand};
+
+/// Pileup record with `n_mod` of 10 valid reads modified, for the summary tests.
+pub fn record(n_mod: u32) -> PileupRecord {
+    record_at(0, Strand::Positive, ModType::SixMA, n_mod)
+}
+
+/// Pileup record of a base of a test contig with `n_mod` of 10 valid reads modified.
+pub fn record_at(position: usize, strand: Strand, mod_type: ModType, n_mod: u32) -> PileupRecord {
+    PileupRecord {
+        reference: "test".to_string(),
+        position,
+        strand,
+        mod_type,
+        n_mod,
+        n_valid_cov: 10,
+        n_canonical: 10 - n_mod,
+        n_diff: 0,
+    }
+}
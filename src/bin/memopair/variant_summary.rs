@@ -0,0 +1,104 @@
+use ahash::{HashMap, HashMapExt};
+use anyhow::Result;
+use std::fs::File;
+use utils::pileup::PileupRecord;
+
+/// Methylation of the site pairs of one concrete motif variant, e.g. `CCAGG` of `CCWGG`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct VariantStats {
+    n_sites: u32,
+    methylation_1: f64,
+    methylation_2: f64,
+    methylation_difference: f64,
+}
+
+/// Collects the site pairs reported for each motif split by the sequence they matched.
+#[derive(Debug, Default)]
+pub struct VariantSummary {
+    variants: HashMap<(usize, String), VariantStats>,
+}
+
+impl VariantSummary {
+    pub fn new() -> Self {
+        Self {
+            variants: HashMap::new(),
+        }
+    }
+
+    pub fn add(
+        &mut self,
+        motif_idx: usize,
+        matched_sequence: &str,
+        record_1: &PileupRecord,
+        record_2: &PileupRecord,
+    ) {
+        let methylation_1 = record_1.n_mod as f64 / record_1.n_valid_cov as f64;
+        let methylation_2 = record_2.n_mod as f64 / record_2.n_valid_cov as f64;
+        let stats = self
+            .variants
+            .entry((motif_idx, matched_sequence.to_string()))
+            .or_default();
+        stats.n_sites += 1;
+        stats.methylation_1 += methylation_1;
+        stats.methylation_2 += methylation_2;
+        stats.methylation_difference += (methylation_1 - methylation_2).abs();
+    }
+
+    /// Writes the mean methylation of each variant, ordered by motif and variant.
+    pub fn write(&self, out_path: &str, reference: &str, motif_names: &[String]) -> Result<(), anyhow::Error> {
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(b'\t')
+            .from_path(out_path)?;
+        write_header(&mut writer)?;
+
+        let mut variants: Vec<(&(usize, String), &VariantStats)> = self.variants.iter().collect();
+        variants.sort_by(|a, b| a.0.cmp(b.0));
+        for ((motif_idx, matched_sequence), stats) in variants {
+            let n_sites = stats.n_sites as f64;
+            writer.write_record(&[
+                reference.to_string(),
+                motif_names[*motif_idx].clone(),
+                matched_sequence.clone(),
+                stats.n_sites.to_string(),
+                (stats.methylation_1 / n_sites).to_string(),
+                (stats.methylation_2 / n_sites).to_string(),
+                (stats.methylation_difference / n_sites).to_string(),
+            ])?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+fn write_header(writer: &mut csv::Writer<File>) -> Result<(), anyhow::Error> {
+    writer.write_record([
+        "contig_id",
+        "motif",
+        "matched_sequence",
+        "n_sites",
+        "mean_methylation_1",
+        "mean_methylation_2",
+        "mean_methylation_difference",
+    ])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::record;
+
+    #[test]
+    fn test_variant_summary() {
+        let mut summary = VariantSummary::new();
+        summary.add(0, "CCAGG", &record(10), &record(6));
+        summary.add(0, "CCAGG", &record(8), &record(10));
+        summary.add(0, "CCTGG", &record(2), &record(2));
+
+        let stats = summary.variants[&(0, "CCAGG".to_string())];
+        assert_eq!(stats.n_sites, 2);
+        assert!((stats.methylation_1 / 2.0 - 0.9).abs() < 1e-9);
+        assert!((stats.methylation_difference / 2.0 - 0.3).abs() < 1e-9);
+        assert_eq!(summary.variants[&(0, "CCTGG".to_string())].n_sites, 1);
+    }
+}
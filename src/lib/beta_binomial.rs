@@ -0,0 +1,143 @@
+use crate::stats::regularized_incomplete_beta;
+
+/// Bounds of the intra-site correlation, keeping the prior proper when the counts show
+/// no overdispersion or more than a beta-binomial can explain. The lower bound limits the
+/// prior to about 100 pseudo reads, so that well covered sites can still differ from the
+/// motif mean.
+const MIN_OVERDISPERSION: f64 = 0.01;
+const MAX_OVERDISPERSION: f64 = 1.0 - 1e-6;
+
+/// Beta prior of the methylation level of sites, fitted to the modified and total read
+/// counts of many sites as the beta-binomial mixing distribution.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BetaPrior {
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+impl BetaPrior {
+    /// Fits the prior by the method of moments of Kleinman (1973) to `(n_mod, n_total)`
+    /// counts per site, giving `None` without any reads.
+    ///
+    /// The mean is the pooled fraction of modified reads and the concentration follows
+    /// from the excess of the between-site variance over the binomial variance.
+    pub fn fit(counts: &[(u32, u32)]) -> Option<Self> {
+        let counts: Vec<(f64, f64)> = counts
+            .iter()
+            .filter(|(_, n)| *n > 0)
+            .map(|&(x, n)| (x as f64, n as f64))
+            .collect();
+        let n_total: f64 = counts.iter().map(|(_, n)| n).sum();
+        if n_total == 0.0 {
+            return None;
+        }
+        let n_mod: f64 = counts.iter().map(|(x, _)| x).sum();
+        // Pseudo counts keep the mean inside (0, 1)
+        let mean = (n_mod + 0.5) / (n_total + 1.0);
+
+        let pooled = n_mod / n_total;
+        let k = counts.len() as f64;
+        let spread: f64 = counts
+            .iter()
+            .map(|(x, n)| n * (x / n - pooled).powi(2))
+            .sum();
+        let n_squared: f64 = counts.iter().map(|(_, n)| n * n).sum();
+        let binomial = pooled * (1.0 - pooled);
+        let denominator = binomial * (n_total - n_squared / n_total - (k - 1.0));
+        let overdispersion = if denominator > 0.0 {
+            (spread - binomial * (k - 1.0)) / denominator
+        } else {
+            MIN_OVERDISPERSION
+        };
+        let overdispersion = overdispersion.clamp(MIN_OVERDISPERSION, MAX_OVERDISPERSION);
+
+        let concentration = (1.0 - overdispersion) / overdispersion;
+        Some(Self {
+            alpha: mean * concentration,
+            beta: (1.0 - mean) * concentration,
+        })
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.alpha / (self.alpha + self.beta)
+    }
+
+    /// Correlation of reads from the same site, `1 / (alpha + beta + 1)`.
+    pub fn overdispersion(&self) -> f64 {
+        1.0 / (self.alpha + self.beta + 1.0)
+    }
+
+    /// Posterior of a site with `n_mod` of `n_total` reads modified.
+    pub fn posterior(&self, n_mod: u32, n_total: u32) -> Self {
+        Self {
+            alpha: self.alpha + n_mod as f64,
+            beta: self.beta + (n_total - n_mod) as f64,
+        }
+    }
+
+    /// Probability that the methylation level is at least `threshold`.
+    pub fn probability_at_least(&self, threshold: f64) -> f64 {
+        1.0 - regularized_incomplete_beta(threshold, self.alpha, self.beta)
+    }
+}
+
+/// Posterior probability that exactly one of two independent sites has a methylation
+/// level of at least `threshold`, i.e. that the pair is hemimethylated.
+pub fn hemimethylation_probability(site_1: &BetaPrior, site_2: &BetaPrior, threshold: f64) -> f64 {
+    let p_1 = site_1.probability_at_least(threshold);
+    let p_2 = site_2.probability_at_least(threshold);
+    p_1 * (1.0 - p_2) + p_2 * (1.0 - p_1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fit() {
+        assert_eq!(BetaPrior::fit(&[]), None);
+        assert_eq!(BetaPrior::fit(&[(0, 0)]), None);
+
+        // Identical sites show no overdispersion, pooling them into a tight prior
+        let prior = BetaPrior::fit(&[(5, 10); 20]).unwrap();
+        assert!((prior.mean() - 100.5 / 201.0).abs() < 1e-12);
+        assert!((prior.overdispersion() - MIN_OVERDISPERSION).abs() < 1e-9);
+
+        // Sites split into unmethylated and methylated ones are highly overdispersed
+        let counts: Vec<(u32, u32)> = (0..20)
+            .map(|i| (if i % 2 == 0 { 1 } else { 19 }, 20))
+            .collect();
+        let prior = BetaPrior::fit(&counts).unwrap();
+        assert!((prior.mean() - 0.5).abs() < 1e-12);
+        assert!(prior.overdispersion() > 0.5);
+    }
+
+    #[test]
+    fn test_posterior() {
+        let prior = BetaPrior {
+            alpha: 2.0,
+            beta: 2.0,
+        };
+        let posterior = prior.posterior(8, 10);
+        assert_eq!(
+            posterior,
+            BetaPrior {
+                alpha: 10.0,
+                beta: 4.0
+            }
+        );
+        assert!((posterior.mean() - 10.0 / 14.0).abs() < 1e-12);
+        // Uniform prior, P(p >= 0.5) = 0.5
+        let uniform = BetaPrior {
+            alpha: 1.0,
+            beta: 1.0,
+        };
+        assert!((uniform.probability_at_least(0.5) - 0.5).abs() < 1e-12);
+        assert!((hemimethylation_probability(&uniform, &uniform, 0.5) - 0.5).abs() < 1e-12);
+
+        let methylated = prior.posterior(20, 20);
+        let unmethylated = prior.posterior(0, 20);
+        assert!(hemimethylation_probability(&methylated, &unmethylated, 0.5) > 0.99);
+        assert!(hemimethylation_probability(&methylated, &methylated, 0.5) < 0.01);
+    }
+}
@@ -0,0 +1,141 @@
+use std::fmt;
+
+/// 0-based position on a contig, as used internally and in pileup files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Position(usize);
+
+impl Position {
+    pub fn new(position: usize) -> Self {
+        Self(position)
+    }
+
+    pub fn get(&self) -> usize {
+        self.0
+    }
+
+    /// Shifts the position by `offset` bases on a contig of length `contig_len`.
+    ///
+    /// Circular contigs wrap around the origin. On linear contigs positions before the
+    /// start or past the end give `None` instead of under- or overflowing.
+    pub fn shifted(&self, offset: isize, contig_len: usize, circular: bool) -> Option<Self> {
+        if circular && contig_len > 0 {
+            let offset = offset.rem_euclid(contig_len as isize) as usize;
+            Some(Self((self.0 % contig_len + offset) % contig_len))
+        } else {
+            self.0
+                .checked_add_signed(offset)
+                .filter(|&position| position < contig_len)
+                .map(Self)
+        }
+    }
+}
+
+/// Half-open interval `start..end` of 0-based positions on a contig.
+///
+/// On circular contigs an interval crossing the origin keeps its length, so `end` lies
+/// past the end of the contig. [`CoordinateSystem::interval`] wraps it for output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Interval {
+    start: Position,
+    end: usize,
+}
+
+impl Interval {
+    pub fn new(start: Position, len: usize) -> Self {
+        Self {
+            start,
+            end: start.get() + len,
+        }
+    }
+
+    pub fn start(&self) -> Position {
+        self.start
+    }
+
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start.get()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Coordinate convention used for positions in output files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CoordinateSystem {
+    /// 0-based, half-open intervals as in BED and bedMethyl files.
+    #[default]
+    Bed,
+    /// 1-based, closed intervals as in GFF and VCF files.
+    OneBased,
+}
+
+impl CoordinateSystem {
+    pub fn position(&self, position: Position) -> usize {
+        match self {
+            CoordinateSystem::Bed => position.get(),
+            CoordinateSystem::OneBased => position.get() + 1,
+        }
+    }
+
+    /// First and last coordinate of `interval` on a contig of `contig_len` bases, the last
+    /// being exclusive for BED.
+    ///
+    /// The end of an interval crossing the origin of a circular contig is wrapped around
+    /// to its last base after the origin, so that it is smaller than the start.
+    pub fn interval(&self, interval: &Interval, contig_len: usize) -> (usize, usize) {
+        let end = match interval.end() {
+            end if end > contig_len && contig_len > 0 => (end - 1) % contig_len + 1,
+            end => end,
+        };
+        (self.position(interval.start()), end)
+    }
+}
+
+impl fmt::Display for CoordinateSystem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CoordinateSystem::Bed => write!(f, "bed"),
+            CoordinateSystem::OneBased => write!(f, "one-based"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_position_shifted() {
+        let position = Position::new(2);
+        assert_eq!(position.shifted(3, 10, false), Some(Position::new(5)));
+        assert_eq!(position.shifted(-3, 10, false), None);
+        assert_eq!(position.shifted(8, 10, false), None);
+        assert_eq!(position.shifted(isize::MIN, 10, false), None);
+
+        assert_eq!(position.shifted(-3, 10, true), Some(Position::new(9)));
+        assert_eq!(position.shifted(8, 10, true), Some(Position::new(0)));
+        assert_eq!(position.shifted(-23, 10, true), Some(Position::new(9)));
+        assert_eq!(position.shifted(1, 0, true), None);
+    }
+
+    #[test]
+    fn test_coordinate_systems() {
+        let interval = Interval::new(Position::new(8), 4);
+        assert_eq!(interval.len(), 4);
+        assert_eq!(CoordinateSystem::Bed.interval(&interval, 20), (8, 12));
+        assert_eq!(CoordinateSystem::OneBased.interval(&interval, 20), (9, 12));
+        // Crossing the origin of a contig of length 10, covering 8, 9, 0 and 1
+        assert_eq!(CoordinateSystem::Bed.interval(&interval, 10), (8, 2));
+        assert_eq!(CoordinateSystem::OneBased.interval(&interval, 10), (9, 2));
+        let interval = Interval::new(Position::new(6), 4);
+        assert_eq!(CoordinateSystem::Bed.interval(&interval, 10), (6, 10));
+        assert_eq!(CoordinateSystem::OneBased.position(Position::new(0)), 1);
+        assert_eq!(CoordinateSystem::OneBased.to_string(), "one-based");
+    }
+}
@@ -0,0 +1,220 @@
+use crate::stats::ConfidenceInterval;
+
+/// Whether site 1 and site 2 are methylated in each duplex state, in the order fully
+/// methylated, only site 1, only site 2 and unmethylated.
+pub const DUPLEX_STATES: [(bool, bool); 4] = [(true, true), (true, false), (false, true), (false, false)];
+
+/// Half the 95% quantile of a chi-squared distribution with one degree of freedom, the
+/// drop in log-likelihood at the bounds of a profile likelihood interval.
+const PROFILE_DROP_95: f64 = 1.920_729_410_347_062;
+
+/// Bounds of the fraction of modified reads of a strand, keeping the log-likelihood finite.
+const MIN_RATE: f64 = 1e-6;
+const MAX_RATE: f64 = 1.0 - 1e-6;
+
+const MAX_EM_ITERATIONS: usize = 1_000;
+const EM_TOLERANCE: f64 = 1e-9;
+
+/// Bisection steps for the interval bounds, fewer than in `stats` as each step refits
+/// the model.
+const PROFILE_STEPS: usize = 40;
+
+/// Latent class model of duplex methylation states fitted to the read counts of site
+/// pairs `[n_mod_1, n_nomod_1, n_mod_2, n_nomod_2]`.
+///
+/// Assumes that all duplexes of a site pair are in the same state, so that the reads of
+/// the two strands are independent given the state. A methylated site gives modified
+/// reads at `methylated_rate` and an unmethylated site at `unmethylated_rate`, shared by
+/// all site pairs but fitted separately for site 1 and site 2, as their mod types and
+/// calling accuracies may differ. The state fractions are then the fractions of duplexes
+/// in each state.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DuplexMixture {
+    /// Fractions of the states in the order of [`DUPLEX_STATES`].
+    pub fractions: [f64; 4],
+    /// Fractions of modified reads of unmethylated sites, of site 1 and site 2.
+    pub unmethylated_rate: [f64; 2],
+    /// Fractions of modified reads of methylated sites, of site 1 and site 2.
+    pub methylated_rate: [f64; 2],
+    /// Log-likelihood without the binomial coefficients.
+    pub log_likelihood: f64,
+}
+
+impl DuplexMixture {
+    /// Fits the model by expectation maximisation, giving `None` without any site pairs.
+    pub fn fit(tables: &[[u32; 4]]) -> Option<Self> {
+        if tables.is_empty() {
+            return None;
+        }
+        let start = Self {
+            fractions: [0.25; 4],
+            unmethylated_rate: [0.05; 2],
+            methylated_rate: [0.95; 2],
+            log_likelihood: f64::NEG_INFINITY,
+        };
+        Some(start.expectation_maximisation(tables, None))
+    }
+
+    /// Log-likelihood of every state for a site pair, including the state fraction.
+    fn state_log_likelihoods(&self, table: &[u32; 4]) -> [f64; 4] {
+        let [x_1, y_1, x_2, y_2] = table.map(|n| n as f64);
+        let site = |i: usize, methylated: bool, x: f64, y: f64| {
+            let rate = if methylated {
+                self.methylated_rate[i]
+            } else {
+                self.unmethylated_rate[i]
+            };
+            x * rate.ln() + y * (1.0 - rate).ln()
+        };
+        let mut log_likelihoods = [f64::NEG_INFINITY; 4];
+        for (state, &(methylated_1, methylated_2)) in DUPLEX_STATES.iter().enumerate() {
+            if self.fractions[state] > 0.0 {
+                log_likelihoods[state] = self.fractions[state].ln()
+                    + site(0, methylated_1, x_1, y_1)
+                    + site(1, methylated_2, x_2, y_2);
+            }
+        }
+        log_likelihoods
+    }
+
+    /// Runs EM from `self`, keeping the fraction of one state fixed if given.
+    fn expectation_maximisation(mut self, tables: &[[u32; 4]], fixed: Option<(usize, f64)>) -> Self {
+        let mut previous = f64::NEG_INFINITY;
+        for _ in 0..MAX_EM_ITERATIONS {
+            let mut weights = [0.0; 4];
+            // Expected modified and total reads of unmethylated and methylated site 1 and 2
+            let (mut modified, mut total) = ([[0.0; 2]; 2], [[0.0; 2]; 2]);
+            let mut log_likelihood = 0.0;
+            for table in tables.iter() {
+                let log_likelihoods = self.state_log_likelihoods(table);
+                let max = log_likelihoods.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+                let sum: f64 = log_likelihoods.iter().map(|l| (l - max).exp()).sum();
+                log_likelihood += max + sum.ln();
+                for (state, &(methylated_1, methylated_2)) in DUPLEX_STATES.iter().enumerate() {
+                    let weight = (log_likelihoods[state] - max).exp() / sum;
+                    weights[state] += weight;
+                    for (i, methylated, x, n) in [
+                        (0, methylated_1, table[0], table[0] + table[1]),
+                        (1, methylated_2, table[2], table[2] + table[3]),
+                    ] {
+                        modified[i][methylated as usize] += weight * x as f64;
+                        total[i][methylated as usize] += weight * n as f64;
+                    }
+                }
+            }
+            self.log_likelihood = log_likelihood;
+            if log_likelihood - previous < EM_TOLERANCE * log_likelihood.abs().max(1.0) {
+                break;
+            }
+            previous = log_likelihood;
+
+            self.fractions = match fixed {
+                None => weights.map(|w| w / tables.len() as f64),
+                Some((fixed_state, fraction)) => {
+                    let other: f64 = (0..4).filter(|&s| s != fixed_state).map(|s| weights[s]).sum();
+                    let mut fractions = [0.0; 4];
+                    for (state, value) in fractions.iter_mut().enumerate() {
+                        *value = if state == fixed_state {
+                            fraction
+                        } else if other > 0.0 {
+                            (1.0 - fraction) * weights[state] / other
+                        } else {
+                            (1.0 - fraction) / 3.0
+                        };
+                    }
+                    fractions
+                }
+            };
+            for i in 0..2 {
+                let rate = |j: usize, default: f64| {
+                    if total[i][j] > 0.0 {
+                        (modified[i][j] / total[i][j]).clamp(MIN_RATE, MAX_RATE)
+                    } else {
+                        default
+                    }
+                };
+                self.unmethylated_rate[i] = rate(0, self.unmethylated_rate[i]);
+                self.methylated_rate[i] = rate(1, self.methylated_rate[i]);
+            }
+        }
+        self
+    }
+
+    /// Profile likelihood 95% confidence interval of the fraction of `state`, an index
+    /// into [`DUPLEX_STATES`], refitting the other parameters at every fraction tried.
+    ///
+    /// Each bound takes up to `PROFILE_STEPS + 1` refits, run to convergence like the fit
+    /// so that the profile is not underestimated. Every refit starts from the previous
+    /// one, which is close after the first few bisection steps, so most converge within
+    /// a few passes over the site pairs.
+    pub fn profile_interval(&self, tables: &[[u32; 4]], state: usize) -> ConfidenceInterval {
+        let target = self.log_likelihood - PROFILE_DROP_95;
+        let profile = |previous: &mut Self, fraction: f64| {
+            let mut start = *previous;
+            let other = 1.0 - start.fractions[state];
+            for (s, value) in start.fractions.iter_mut().enumerate() {
+                *value = if s == state {
+                    fraction
+                } else if other > 0.0 {
+                    *value * (1.0 - fraction) / other
+                } else {
+                    (1.0 - fraction) / 3.0
+                };
+            }
+            *previous = start.expectation_maximisation(tables, Some((state, fraction)));
+            previous.log_likelihood
+        };
+        // The profile rises up to the estimate and falls after it
+        let bound = |mut inside: f64, mut outside: f64| {
+            let mut previous = *self;
+            if profile(&mut previous, outside) >= target {
+                return outside;
+            }
+            for _ in 0..PROFILE_STEPS {
+                let mid = 0.5 * (inside + outside);
+                if profile(&mut previous, mid) >= target {
+                    inside = mid;
+                } else {
+                    outside = mid;
+                }
+            }
+            0.5 * (inside + outside)
+        };
+        let estimate = self.fractions[state];
+        ConfidenceInterval {
+            low: bound(estimate, 0.0),
+            high: bound(estimate, 1.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_duplex_mixture() {
+        // 12 fully methylated, 6 hemimethylated on site 1 and 2 unmethylated site pairs
+        let mut tables = vec![[19, 1, 20, 0]; 12];
+        tables.extend(vec![[20, 0, 1, 19]; 6]);
+        tables.extend(vec![[0, 20, 1, 19]; 2]);
+        let mixture = DuplexMixture::fit(&tables).unwrap();
+        let expected = [0.6, 0.3, 0.0, 0.1];
+        for (fraction, expected) in mixture.fractions.iter().zip(expected.iter()) {
+            assert!((fraction - expected).abs() < 1e-3, "{:?}", mixture.fractions);
+        }
+        assert_eq!(mixture.unmethylated_rate[0], MIN_RATE);
+        assert!((mixture.unmethylated_rate[1] - 0.05).abs() < 1e-3);
+        assert!((mixture.methylated_rate[0] - 348.0 / 360.0).abs() < 1e-3);
+        assert_eq!(mixture.methylated_rate[1], MAX_RATE);
+
+        let interval = mixture.profile_interval(&tables, 1);
+        assert!(interval.low > 0.1 && interval.low < 0.3);
+        assert!(interval.high > 0.3 && interval.high < 0.55);
+        let interval = mixture.profile_interval(&tables, 2);
+        assert_eq!(interval.low, 0.0);
+        assert!(interval.high > 0.0 && interval.high < 0.2);
+
+        assert!(DuplexMixture::fit(&[]).is_none());
+    }
+}
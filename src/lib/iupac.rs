@@ -87,24 +87,103 @@ impl IupacBase {
         }
     }
 
+    /// Nucleotides represented by the base as a 4-bit mask, with A, C, G and T as bits 0 to 3.
+    pub fn to_mask(&self) -> u8 {
+        match self {
+            IupacBase::A => 0b0001,
+            IupacBase::C => 0b0010,
+            IupacBase::G => 0b0100,
+            IupacBase::T => 0b1000,
+            IupacBase::R => 0b0101,
+            IupacBase::Y => 0b1010,
+            IupacBase::S => 0b0110,
+            IupacBase::W => 0b1001,
+            IupacBase::K => 0b1100,
+            IupacBase::M => 0b0011,
+            IupacBase::B => 0b1110,
+            IupacBase::D => 0b1101,
+            IupacBase::H => 0b1011,
+            IupacBase::V => 0b0111,
+            IupacBase::N => 0b1111,
+        }
+    }
+
+    /// Inverse of [`IupacBase::to_mask`]. Returns `None` for the empty mask.
+    pub fn from_mask(mask: u8) -> Option<IupacBase> {
+        match mask & 0b1111 {
+            0b0001 => Some(IupacBase::A),
+            0b0010 => Some(IupacBase::C),
+            0b0100 => Some(IupacBase::G),
+            0b1000 => Some(IupacBase::T),
+            0b0101 => Some(IupacBase::R),
+            0b1010 => Some(IupacBase::Y),
+            0b0110 => Some(IupacBase::S),
+            0b1001 => Some(IupacBase::W),
+            0b1100 => Some(IupacBase::K),
+            0b0011 => Some(IupacBase::M),
+            0b1110 => Some(IupacBase::B),
+            0b1101 => Some(IupacBase::D),
+            0b1011 => Some(IupacBase::H),
+            0b0111 => Some(IupacBase::V),
+            0b1111 => Some(IupacBase::N),
+            _ => None,
+        }
+    }
+
+    /// Number of nucleotides the base represents, 1 for A, C, G and T and 4 for N.
+    pub fn degeneracy(&self) -> u8 {
+        self.to_mask().count_ones() as u8
+    }
+
+    /// Checks whether every nucleotide of `base` is represented by this base,
+    /// e.g. `R` matches `A`, `G` and `R`, but not `N`.
+    pub fn matches(&self, base: &IupacBase) -> bool {
+        base.is_subset_of(self)
+    }
+
+    pub fn is_subset_of(&self, other: &IupacBase) -> bool {
+        self.to_mask() & !other.to_mask() == 0
+    }
+
+    /// Nucleotides shared by both bases, or `None` if they have none in common.
+    pub fn intersection(&self, other: &IupacBase) -> Option<IupacBase> {
+        IupacBase::from_mask(self.to_mask() & other.to_mask())
+    }
+
+    /// The unambiguous bases represented by this base.
+    pub fn expand(&self) -> Vec<IupacBase> {
+        [IupacBase::A, IupacBase::C, IupacBase::G, IupacBase::T]
+            .into_iter()
+            .filter(|base| self.matches(base))
+            .collect()
+    }
+
+    /// Parses an uppercase ASCII IUPAC code, giving `None` for anything else.
+    pub fn from_byte(byte: u8) -> Option<IupacBase> {
+        match byte {
+            b'A' => Some(IupacBase::A),
+            b'C' => Some(IupacBase::C),
+            b'G' => Some(IupacBase::G),
+            b'T' => Some(IupacBase::T),
+            b'R' => Some(IupacBase::R),
+            b'Y' => Some(IupacBase::Y),
+            b'S' => Some(IupacBase::S),
+            b'W' => Some(IupacBase::W),
+            b'K' => Some(IupacBase::K),
+            b'M' => Some(IupacBase::M),
+            b'B' => Some(IupacBase::B),
+            b'D' => Some(IupacBase::D),
+            b'H' => Some(IupacBase::H),
+            b'V' => Some(IupacBase::V),
+            b'N' => Some(IupacBase::N),
+            _ => None,
+        }
+    }
+
     pub fn from_char(c: char) -> Result<IupacBase, anyhow::Error> {
-        match c {
-            'A' => Ok(IupacBase::A),
-            'C' => Ok(IupacBase::C),
-            'G' => Ok(IupacBase::G),
-            'T' => Ok(IupacBase::T),
-            'R' => Ok(IupacBase::R),
-            'Y' => Ok(IupacBase::Y),
-            'S' => Ok(IupacBase::S),
-            'W' => Ok(IupacBase::W),
-            'K' => Ok(IupacBase::K),
-            'M' => Ok(IupacBase::M),
-            'B' => Ok(IupacBase::B),
-            'D' => Ok(IupacBase::D),
-            'H' => Ok(IupacBase::H),
-            'V' => Ok(IupacBase::V),
-            'N' => Ok(IupacBase::N),
-            _ => bail!("Invalid IUPAC base: {}", c),
+        match u8::try_from(c).ok().and_then(IupacBase::from_byte) {
+            Some(base) => Ok(base),
+            None => bail!("Invalid IUPAC base: {}", c),
         }
     }
 }
@@ -115,7 +194,7 @@ mod tests {
 
     #[test]
     fn test_to_regex() {
-        for base in vec![
+        for base in [
             IupacBase::A,
             IupacBase::C,
             IupacBase::G,
@@ -156,7 +235,7 @@ mod tests {
 
     #[test]
     fn test_complement() {
-        for base in vec![
+        for base in [
             IupacBase::A,
             IupacBase::C,
             IupacBase::G,
@@ -194,4 +273,56 @@ mod tests {
             assert_eq!(complement, expected)
         }
     }
+
+    #[test]
+    fn test_mask_round_trip() {
+        for base in [
+            IupacBase::A,
+            IupacBase::C,
+            IupacBase::G,
+            IupacBase::T,
+            IupacBase::R,
+            IupacBase::Y,
+            IupacBase::S,
+            IupacBase::W,
+            IupacBase::K,
+            IupacBase::M,
+            IupacBase::B,
+            IupacBase::D,
+            IupacBase::H,
+            IupacBase::V,
+            IupacBase::N,
+        ] {
+            assert_eq!(IupacBase::from_mask(base.to_mask()), Some(base));
+            // Reversing the bit order of the mask swaps A/T and C/G
+            let complement_mask = base.to_mask().reverse_bits() >> 4;
+            assert_eq!(IupacBase::from_mask(complement_mask), Some(base.complement()));
+        }
+        assert_eq!(IupacBase::from_mask(0), None);
+    }
+
+    #[test]
+    fn test_set_operations() {
+        assert_eq!(IupacBase::A.degeneracy(), 1);
+        assert_eq!(IupacBase::R.degeneracy(), 2);
+        assert_eq!(IupacBase::B.degeneracy(), 3);
+        assert_eq!(IupacBase::N.degeneracy(), 4);
+
+        assert!(IupacBase::R.matches(&IupacBase::A));
+        assert!(IupacBase::R.matches(&IupacBase::R));
+        assert!(!IupacBase::R.matches(&IupacBase::C));
+        assert!(!IupacBase::R.matches(&IupacBase::N));
+        assert!(IupacBase::N.matches(&IupacBase::W));
+
+        assert!(IupacBase::A.is_subset_of(&IupacBase::W));
+        assert!(IupacBase::W.is_subset_of(&IupacBase::H));
+        assert!(!IupacBase::H.is_subset_of(&IupacBase::W));
+
+        assert_eq!(IupacBase::R.intersection(&IupacBase::W), Some(IupacBase::A));
+        assert_eq!(IupacBase::B.intersection(&IupacBase::V), Some(IupacBase::S));
+        assert_eq!(IupacBase::A.intersection(&IupacBase::C), None);
+
+        assert_eq!(IupacBase::Y.expand(), vec![IupacBase::C, IupacBase::T]);
+        assert_eq!(IupacBase::N.expand().len(), 4);
+    }
 }
@@ -1,5 +1,14 @@
+pub mod beta_binomial;
+pub mod coordinate;
+pub mod duplex;
 pub mod iupac;
+pub mod markov;
 pub mod modtype;
 pub mod motif;
 pub mod strand;
-pub mod pileup;
\ No newline at end of file
+pub mod packed_sequence;
+pub mod pileup;
+pub mod random;
+pub mod scanner;
+pub mod site_set;
+pub mod stats;
\ No newline at end of file
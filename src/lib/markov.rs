@@ -0,0 +1,181 @@
+use crate::{iupac::IupacBase, packed_sequence::PackedSequence};
+use anyhow::{bail, Result};
+
+/// Highest supported order, keeping the k-mer table at 4^10 entries.
+pub const MAX_ORDER: usize = 9;
+
+/// Index of an unambiguous base given as 4-bit IUPAC mask, or `None` for ambiguous bases.
+fn base_index(code: u8) -> Option<usize> {
+    match code {
+        0b0001 => Some(0),
+        0b0010 => Some(1),
+        0b0100 => Some(2),
+        0b1000 => Some(3),
+        _ => None,
+    }
+}
+
+/// Markov chain background model of a sequence, estimated from its k-mer counts.
+///
+/// Counts are taken from both strands, so the model gives the same probability to a
+/// motif and its reverse complement. Windows containing ambiguous bases are skipped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarkovBackground {
+    order: usize,
+    /// Counts of all words of length `order + 1`, indexed in base 4 with A=0, C=1, G=2, T=3.
+    counts: Vec<u64>,
+}
+
+impl MarkovBackground {
+    pub fn from_sequence(sequence: &PackedSequence, order: usize, circular: bool) -> Result<Self> {
+        if order > MAX_ORDER {
+            bail!("Markov order must be at most {}, got {}", MAX_ORDER, order);
+        }
+        let k = order + 1;
+        let mask = 4usize.pow(k as u32);
+        let mut counts = vec![0u64; mask];
+        let n_codes = if circular && sequence.len() >= k {
+            sequence.len() + k - 1
+        } else {
+            sequence.len()
+        };
+        let (mut index, mut valid) = (0usize, 0usize);
+        for code in sequence.codes().cycle().take(n_codes) {
+            match base_index(code) {
+                Some(base) => {
+                    index = (index * 4 + base) % mask;
+                    valid += 1;
+                }
+                None => valid = 0,
+            }
+            if valid >= k {
+                counts[index] += 1;
+            }
+        }
+
+        // Add the negative strand
+        let forward = counts.clone();
+        for (index, count) in forward.into_iter().enumerate() {
+            counts[reverse_complement_index(index, k)] += count;
+        }
+        Ok(Self { order, counts })
+    }
+
+    pub fn order(&self) -> usize {
+        self.order
+    }
+
+    /// Probability that `motif` occurs at a given position of one strand.
+    ///
+    /// Degenerate motif bases sum over the bases they match. The first `order` bases are
+    /// drawn from the word frequencies and the rest from the transition probabilities, so
+    /// motifs no longer than `order + 1` are drawn from the word frequencies alone.
+    pub fn motif_probability(&self, motif: &[IupacBase]) -> f64 {
+        let total: u64 = self.counts.iter().sum();
+        if total == 0 || motif.is_empty() {
+            return 0.0;
+        }
+        let k = self.order + 1;
+        let word_matches = |index: usize, n: usize| {
+            (0..n).all(|i| {
+                let base = (index / 4usize.pow((k - 1 - i) as u32)) % 4;
+                motif[i].to_mask() & (1 << base) != 0
+            })
+        };
+        if motif.len() <= k {
+            return self
+                .counts
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| word_matches(*index, motif.len()))
+                .map(|(_, &count)| count as f64 / total as f64)
+                .sum();
+        }
+
+        // Probability of each context of `order` bases matching the start of the motif
+        let n_states = 4usize.pow(self.order as u32);
+        let mut state = vec![0.0; n_states];
+        for (index, &count) in self.counts.iter().enumerate() {
+            if word_matches(index, self.order) {
+                state[index / 4] += count as f64 / total as f64;
+            }
+        }
+        // Extend one base at a time, keeping the last `order` bases as the chain state
+        for base in motif.iter().skip(self.order) {
+            let mut next = vec![0.0; n_states];
+            for (context, &probability) in state.iter().enumerate() {
+                let context_total: u64 = self.counts[context * 4..context * 4 + 4].iter().sum();
+                if probability == 0.0 || context_total == 0 {
+                    continue;
+                }
+                for b in (0..4).filter(|b| base.to_mask() & (1 << b) != 0) {
+                    let transition = self.counts[context * 4 + b] as f64 / context_total as f64;
+                    next[(context * 4 + b) % n_states] += probability * transition;
+                }
+            }
+            state = next;
+        }
+        state.iter().sum()
+    }
+}
+
+fn reverse_complement_index(index: usize, k: usize) -> usize {
+    let mut rc = 0;
+    let mut rest = index;
+    for _ in 0..k {
+        rc = rc * 4 + (3 - rest % 4);
+        rest /= 4;
+    }
+    rc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn motif(sequence: &str) -> Vec<IupacBase> {
+        sequence.chars().map(|c| IupacBase::from_char(c).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_reverse_complement_index() {
+        // AAC (0, 0, 1) and GTT (2, 3, 3)
+        assert_eq!(reverse_complement_index(1, 3), 2 * 16 + 3 * 4 + 3);
+        assert_eq!(reverse_complement_index(reverse_complement_index(27, 4), 4), 27);
+    }
+
+    #[test]
+    fn test_order_zero() {
+        let sequence = PackedSequence::from_bytes(b"AACCNAAC");
+        let background = MarkovBackground::from_sequence(&sequence, 0, false).unwrap();
+        // 4 A, 3 C on the positive strand plus 4 T, 3 G on the negative strand
+        assert!((background.motif_probability(&motif("A")) - 4.0 / 14.0).abs() < 1e-12);
+        assert!((background.motif_probability(&motif("AC")) - 4.0 * 3.0 / 196.0).abs() < 1e-12);
+        assert!((background.motif_probability(&motif("NN")) - 1.0).abs() < 1e-12);
+        assert!((background.motif_probability(&motif("S")) - 6.0 / 14.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_order_one() {
+        let sequence = PackedSequence::from_bytes(b"ACGTACGTAC");
+        let background = MarkovBackground::from_sequence(&sequence, 1, false).unwrap();
+        assert_eq!(background.order(), 1);
+        // Every base is followed by its successor in ACGT, on both strands
+        let p_a = background.motif_probability(&motif("A"));
+        assert!((background.motif_probability(&motif("ACGT")) - p_a).abs() < 1e-12);
+        assert_eq!(background.motif_probability(&motif("AAGT")), 0.0);
+        // AC, CG, GT, TA on the positive strand plus GT, CG, AC, TA on the negative strand
+        assert!((background.motif_probability(&motif("GT")) - 5.0 / 18.0).abs() < 1e-12);
+        assert!((background.motif_probability(&motif("CG")) - 4.0 / 18.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_circular_and_max_order() {
+        let sequence = PackedSequence::from_bytes(b"ACGT");
+        let linear = MarkovBackground::from_sequence(&sequence, 1, false).unwrap();
+        let circular = MarkovBackground::from_sequence(&sequence, 1, true).unwrap();
+        assert_eq!(linear.motif_probability(&motif("TA")), 0.0);
+        assert!(circular.motif_probability(&motif("TA")) > 0.0);
+        assert!(MarkovBackground::from_sequence(&sequence, MAX_ORDER + 1, false).is_err());
+    }
+}
@@ -64,7 +64,7 @@ mod tests {
 
     #[test]
     fn test_to_pileup_code() {
-        for mt in vec![ModType::SixMA, ModType::FiveMC, ModType::FourMC] {
+        for mt in [ModType::SixMA, ModType::FiveMC, ModType::FourMC] {
             let pileup_code = mt.to_pileup_code();
             let expected = match mt {
                 ModType::SixMA => "a",
@@ -77,7 +77,7 @@ mod tests {
 
     #[test]
     fn test_display() {
-        for mt in vec![ModType::SixMA, ModType::FiveMC, ModType::FourMC] {
+        for mt in [ModType::SixMA, ModType::FiveMC, ModType::FourMC] {
             let display = format!("{}", mt);
             let expected = match mt {
                 ModType::SixMA => "6mA, (a)",
@@ -90,7 +90,7 @@ mod tests {
 
     #[test]
     fn test_from_str() {
-        for (s, expected) in vec![
+        for (s, expected) in [
             ("5mC", ModType::FiveMC),
             ("m", ModType::FiveMC),
             ("4mC", ModType::FourMC),
@@ -104,7 +104,7 @@ mod tests {
     }
     #[test]
     fn test_from_str_error() {
-        for s in vec!["5mc", "4mc", "6ma", "b"] {
+        for s in ["5mc", "4mc", "6ma", "b"] {
             let modtype = s.parse::<ModType>();
             assert!(modtype.is_err())
         }
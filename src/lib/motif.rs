@@ -1,5 +1,6 @@
-use crate::{iupac::IupacBase, modtype::ModType};
+use crate::{iupac::IupacBase, modtype::ModType, strand::Strand};
 use anyhow::Result;
+use std::{fmt, str::FromStr};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ComplementMotif {
@@ -21,8 +22,94 @@ pub struct Motif {
     pub position: u8,
 }
 
+/// Longest motif sequence after expanding gaps, the width of a scanner state word.
+pub const MAX_MOTIF_LENGTH: usize = 64;
+
+/// Expands compact gap notation in a motif sequence to a plain IUPAC sequence.
+///
+/// A base can be repeated with `N{7}` or `N(7)`, and `(N7)` is accepted as well, so
+/// `CCAN(7)GTGG` and `CCA(N7)GTGG` both become `CCANNNNNNNGTGG`. Lowercase bases are
+/// converted to uppercase, giving the canonical form used for output. Fails for motifs
+/// longer than [`MAX_MOTIF_LENGTH`] bases.
+pub fn expand_motif_sequence(sequence: &str) -> Result<String, anyhow::Error> {
+    let chars: Vec<char> = sequence.chars().collect();
+    let mut expanded = String::with_capacity(chars.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let (base, count, next) = match c {
+            '(' => {
+                // (N7) style group
+                let close = find_closing(&chars, i, ')', sequence)?;
+                let base = chars.get(i + 1).copied().filter(|b| b.is_ascii_alphabetic());
+                let base = base.ok_or_else(|| {
+                    anyhow::anyhow!("Expected a base after '(' in sequence: {}", sequence)
+                })?;
+                let count = parse_repeat_count(&chars[i + 2..close], sequence)?;
+                (base, count, close + 1)
+            }
+            '{' | ')' | '}' => {
+                anyhow::bail!("Unexpected '{}' in sequence: {}", c, sequence)
+            }
+            _ => match chars.get(i + 1) {
+                // N{7} or N(7) style repeat, where a '(' followed by a base starts a (N7) group
+                Some(&open @ ('{' | '('))
+                    if open == '{' || chars.get(i + 2).is_some_and(|c| c.is_ascii_digit()) =>
+                {
+                    let closing = if open == '{' { '}' } else { ')' };
+                    let close = find_closing(&chars, i + 1, closing, sequence)?;
+                    let count = parse_repeat_count(&chars[i + 2..close], sequence)?;
+                    (c, count, close + 1)
+                }
+                _ => (c, 1, i + 1),
+            },
+        };
+        if expanded.len() + count > MAX_MOTIF_LENGTH {
+            anyhow::bail!(
+                "Motifs can be at most {} bases long, gaps included: {}",
+                MAX_MOTIF_LENGTH,
+                sequence
+            );
+        }
+        for _ in 0..count {
+            expanded.push(base.to_ascii_uppercase());
+        }
+        i = next;
+    }
+    Ok(expanded)
+}
+
+fn find_closing(
+    chars: &[char],
+    open: usize,
+    closing: char,
+    sequence: &str,
+) -> Result<usize, anyhow::Error> {
+    chars[open..]
+        .iter()
+        .position(|&c| c == closing)
+        .map(|offset| open + offset)
+        .ok_or_else(|| anyhow::anyhow!("Missing '{}' in sequence: {}", closing, sequence))
+}
+
+fn parse_repeat_count(digits: &[char], sequence: &str) -> Result<usize, anyhow::Error> {
+    let digits: String = digits.iter().collect();
+    match digits.parse::<usize>() {
+        Ok(count) if count > 0 => Ok(count),
+        _ => anyhow::bail!(
+            "Invalid repeat count '{}' in sequence: {}",
+            digits,
+            sequence
+        ),
+    }
+}
+
 impl Motif {
+    /// Creates a motif, where `position` refers to the sequence after expanding gap
+    /// notation, see [`expand_motif_sequence`].
     pub fn new(sequence: &str, mod_type: &str, position: u8) -> Result<Self, anyhow::Error> {
+        let sequence = expand_motif_sequence(sequence)?;
+        let sequence = sequence.as_str();
         let parsed_sequence = sequence
             .chars()
             .map(|c| {
@@ -64,10 +151,87 @@ impl Motif {
             .collect::<String>();
         let position = self.sequence.len() as u8 - self.position - 1;
 
-        let mod_type_str: &str = &self.mod_type.to_string();
+        let mod_type_str: &str = self.mod_type.to_string();
         ComplementMotif::new(&reversed_sequence, mod_type_str, position)
     }
 
+    /// Checks whether every occurrence of `other` is also an occurrence of this motif
+    /// modifying the same base, e.g. `GATC_a_1` contains `RGATCY_a_2`.
+    pub fn contains(&self, other: &Motif) -> bool {
+        match self.aligned_offset(other) {
+            Some(offset) if offset + self.sequence.len() <= other.sequence.len() => self
+                .sequence
+                .iter()
+                .zip(other.sequence[offset..].iter())
+                .all(|(base, other_base)| other_base.is_subset_of(base)),
+            _ => false,
+        }
+    }
+
+    /// Checks whether some sequence is an occurrence of both motifs modifying the same
+    /// base, i.e. the aligned motifs share a nucleotide wherever they overlap.
+    /// `GATC_a_1` overlaps with `RGATCY_a_2` and `GAYNNNNNNRTC_a_1`, but not with `GANTC_a_1`.
+    pub fn overlaps_with(&self, other: &Motif) -> bool {
+        if self.mod_type != other.mod_type {
+            return false;
+        }
+        let shift = other.position as isize - self.position as isize;
+        self.sequence.iter().enumerate().all(|(i, base)| {
+            let j = i as isize + shift;
+            if j < 0 || j >= other.sequence.len() as isize {
+                return true;
+            }
+            base.intersection(&other.sequence[j as usize]).is_some()
+        })
+    }
+
+    /// Start of this motif within `other` when the modified bases are aligned.
+    fn aligned_offset(&self, other: &Motif) -> Option<usize> {
+        if self.mod_type != other.mod_type || other.position < self.position {
+            return None;
+        }
+        Some((other.position - self.position) as usize)
+    }
+
+    /// Number of concrete sequences matched by the motif, saturating at `u64::MAX` for
+    /// motifs with more than 31 `N`s.
+    pub fn degeneracy(&self) -> u64 {
+        self.sequence.iter().fold(1u64, |product, base| {
+            product.saturating_mul(base.degeneracy() as u64)
+        })
+    }
+
+    /// Probability of the motif matching at a position of a uniform random sequence.
+    /// Lower values mean a more specific motif, e.g. `RGATCY` is more specific than `GATC`.
+    pub fn match_probability(&self) -> f64 {
+        self.sequence
+            .iter()
+            .map(|base| base.degeneracy() as f64 / 4.0)
+            .product()
+    }
+
+    /// All concrete sequences matched by the motif, e.g. `CCAGG` and `CCTGG` for `CCWGG`.
+    ///
+    /// The number of variants grows exponentially with the number of degenerate bases,
+    /// see [`Motif::degeneracy`].
+    pub fn expand_variants(&self) -> Vec<String> {
+        let mut variants = vec![String::with_capacity(self.sequence.len())];
+        for base in self.sequence.iter() {
+            let expanded = base.expand();
+            variants = variants
+                .iter()
+                .flat_map(|prefix| {
+                    expanded.iter().map(move |b| {
+                        let mut variant = prefix.clone();
+                        variant.push_str(b.to_string());
+                        variant
+                    })
+                })
+                .collect();
+        }
+        variants
+    }
+
     pub fn reverse_complement_sequence(&self) -> String {
         let reversed_sequence: String = self
             .sequence
@@ -86,7 +250,7 @@ impl MotifLike for Motif {
     fn regex(&self) -> Result<String> {
         let mut regex = String::new();
         for base in self.sequence.iter() {
-            regex.push_str(&base.to_regex());
+            regex.push_str(base.to_regex());
         }
         Ok(regex)
     }
@@ -145,7 +309,7 @@ impl MotifLike for ComplementMotif {
     fn regex(&self) -> Result<String> {
         let mut regex = String::new();
         for base in self.sequence.iter() {
-            regex.push_str(&base.to_regex());
+            regex.push_str(base.to_regex());
         }
         Ok(regex)
     }
@@ -189,6 +353,328 @@ impl MotifPair {
     }
 }
 
+impl FromStr for MotifPair {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s).map(|(pair, _)| pair)
+    }
+}
+
+impl fmt::Display for MotifPair {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}_{}_{}_{}_{}",
+            self.forward.sequence_string(),
+            self.forward.mod_type.to_pileup_code(),
+            self.forward.position,
+            self.reverse.mod_type.to_pileup_code(),
+            self.partner_position()
+        )
+    }
+}
+
+/// How the sites of two motif pairs relate, see [`MotifPair::overlap_with`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MotifOverlap {
+    /// Every site pair of the other motif pair is also a site pair of this one.
+    Subsumes,
+    /// Every site pair of this motif pair is also a site pair of the other one.
+    SubsumedBy,
+    /// Some, but not necessarily all, site pairs can be shared.
+    Overlaps,
+}
+
+impl fmt::Display for MotifOverlap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MotifOverlap::Subsumes => write!(f, "subsumes"),
+            MotifOverlap::SubsumedBy => write!(f, "subsumed by"),
+            MotifOverlap::Overlaps => write!(f, "overlaps"),
+        }
+    }
+}
+
+/// How the partner of a single motif was derived in [`MotifPair::infer`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartnerInference {
+    /// The motif is its own reverse complement, so the partner is the same position on
+    /// the other strand.
+    Palindrome,
+    /// Only one base of the reverse complement can carry the mod type.
+    UniqueCandidate,
+}
+
+impl fmt::Display for PartnerInference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PartnerInference::Palindrome => {
+                write!(
+                    f,
+                    "motif is palindromic, partner is the same position on the complementary strand"
+                )
+            }
+            PartnerInference::UniqueCandidate => write!(
+                f,
+                "only one position on the complementary strand matches the mod type"
+            ),
+        }
+    }
+}
+
+impl MotifPair {
+    /// Parses a motif pair in the format `MOTIF_TYPE1_POS1_TYPE2_POS2`, e.g. `CCWGG_m_1_m_3`,
+    /// where `POS2` is the position of the partner base in the forward motif.
+    ///
+    /// The partner can be left out, as in `GATC_a_1`, and is then derived with
+    /// [`MotifPair::infer`], whose reasoning is returned as well. The pair keeps the
+    /// orientation it was written in, compare [`MotifPair::canonical`] forms to find
+    /// the same pair written from the other strand.
+    pub fn parse(s: &str) -> Result<(Self, Option<PartnerInference>), anyhow::Error> {
+        let parts: Vec<&str> = s.split('_').collect();
+        if parts.len() != 3 && parts.len() != 5 {
+            anyhow::bail!("Invalid motif pair string: {}", s);
+        }
+        let position_1 = parts[2]
+            .parse::<u8>()
+            .map_err(|_| anyhow::anyhow!("Invalid position '{}' in motif pair: {}", parts[2], s))?;
+        let motif_1 = Motif::new(parts[0], parts[1], position_1)?;
+        if parts.len() == 3 {
+            let (pair, inference) = Self::infer(motif_1)?;
+            return Ok((pair, Some(inference)));
+        }
+        let sequence_2 = motif_1.reverse_complement_sequence();
+        let position_2 = parts[4]
+            .parse::<u8>()
+            .map_err(|_| anyhow::anyhow!("Invalid position '{}' in motif pair: {}", parts[4], s))?;
+        if position_2 as usize >= sequence_2.len() {
+            anyhow::bail!(
+                "Position {} is out of bounds for sequence: {}",
+                position_2,
+                motif_1.sequence_string()
+            );
+        }
+        let position_2 = sequence_2.len() as u8 - position_2 - 1;
+        let motif_2 = Motif::new(&sequence_2, parts[3], position_2)?;
+        Ok((Self::new(motif_1, motif_2)?, None))
+    }
+
+    /// Position of the partner base in the forward motif.
+    pub fn partner_position(&self) -> u8 {
+        self.reverse.sequence.len() as u8 - self.reverse.position - 1
+    }
+
+    /// The pair written from the other strand, i.e. with forward and reverse swapped.
+    pub fn swapped(&self) -> Self {
+        Self {
+            forward: self.reverse.clone(),
+            reverse: self.forward.clone(),
+            is_palindromic: self.is_palindromic,
+        }
+    }
+
+    /// Orients the pair so that equivalent specifications from either strand are equal,
+    /// e.g. `TGATC_a_2_a_3` and `GATCA_a_1_a_2` both become `GATCA_a_1_a_2`.
+    ///
+    /// The orientation with the lexicographically smallest forward motif sequence, then
+    /// modified position and mod type, is used.
+    pub fn canonical(self) -> Self {
+        let key = |motif: &Motif| {
+            (
+                motif.sequence_string(),
+                motif.position,
+                motif.mod_type.to_pileup_code(),
+            )
+        };
+        if key(&self.reverse) < key(&self.forward) {
+            self.swapped()
+        } else {
+            self
+        }
+    }
+
+    /// Checks whether the two motif pairs can report the same pair of modified bases,
+    /// with either pair found on either strand.
+    pub fn overlap_with(&self, other: &MotifPair) -> Option<MotifOverlap> {
+        let orientations = [
+            (&other.forward, &other.reverse),
+            (&other.reverse, &other.forward),
+        ];
+        for (other_forward, other_reverse) in orientations {
+            if self.forward.contains(other_forward) && self.reverse.contains(other_reverse) {
+                return Some(MotifOverlap::Subsumes);
+            }
+            if other_forward.contains(&self.forward) && other_reverse.contains(&self.reverse) {
+                return Some(MotifOverlap::SubsumedBy);
+            }
+        }
+        orientations
+            .iter()
+            .any(|(other_forward, other_reverse)| {
+                self.forward.overlaps_with(other_forward) && self.reverse.overlaps_with(other_reverse)
+            })
+            .then_some(MotifOverlap::Overlaps)
+    }
+
+    /// Derives the partner of `forward` from its reverse complement, using the same mod type.
+    ///
+    /// Palindromic motifs pair with the same position on the complementary strand. Other
+    /// motifs pair with the single complementary position carrying the mod type base,
+    /// and fail if there are none or several such positions.
+    pub fn infer(forward: Motif) -> Result<(Self, PartnerInference), anyhow::Error> {
+        let reverse_sequence = forward.reverse_complement_sequence();
+        let (position, inference) = if forward.sequence_string() == reverse_sequence {
+            (forward.position, PartnerInference::Palindrome)
+        } else {
+            let mod_base = forward.mod_type.get_iupac_base();
+            let candidates: Vec<u8> = reverse_sequence
+                .chars()
+                .enumerate()
+                .filter(|(_, c)| IupacBase::from_char(*c).ok().as_ref() == Some(mod_base))
+                .map(|(i, _)| i as u8)
+                .collect();
+            match candidates.as_slice() {
+                [position] => (*position, PartnerInference::UniqueCandidate),
+                [] => anyhow::bail!(
+                    "Cannot infer partner for {}: reverse complement {} has no {} base",
+                    forward.as_string(),
+                    reverse_sequence,
+                    mod_base
+                ),
+                _ => anyhow::bail!(
+                    "Cannot infer partner for {}: reverse complement {} has several {} bases at positions {:?}, specify the partner explicitly",
+                    forward.as_string(),
+                    reverse_sequence,
+                    mod_base,
+                    candidates
+                ),
+            }
+        };
+        let reverse = Motif::new(
+            &reverse_sequence,
+            forward.mod_type.to_pileup_code(),
+            position,
+        )?;
+        Ok((Self::new(forward, reverse)?, inference))
+    }
+}
+
+/// A modified motif base paired with a base at a fixed offset from it.
+///
+/// Unlike [`MotifPair`] the partner need not be the modified base of the reverse
+/// complement motif, it can lie on either strand and outside of the motif. The offset
+/// is counted along the motif and `partner_strand` is relative to the strand the motif
+/// is found on, so `ACGT_a_0_+2_-_m` pairs the A with the complementary C two bases on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OffsetPair {
+    pub motif: Motif,
+    pub offset: isize,
+    pub partner_strand: Strand,
+    pub partner_mod_type: ModType,
+}
+
+impl OffsetPair {
+    pub fn new(
+        motif: Motif,
+        offset: isize,
+        partner_strand: Strand,
+        partner_mod_type: ModType,
+    ) -> Result<Self, anyhow::Error> {
+        if offset == 0 && partner_strand == Strand::Positive {
+            anyhow::bail!("Partner of {} is the modified base itself", motif.as_string());
+        }
+        let pair = Self {
+            motif,
+            offset,
+            partner_strand,
+            partner_mod_type,
+        };
+        // Partners outside of the motif can only be checked against the reference
+        if let Some(base) = usize::try_from(pair.partner_position())
+            .ok()
+            .and_then(|position| pair.motif.sequence.get(position))
+        {
+            let strand_base = match partner_strand {
+                Strand::Positive => *base,
+                Strand::Negative => base.complement(),
+            };
+            if !strand_base.matches(partner_mod_type.get_iupac_base()) {
+                anyhow::bail!(
+                    "Partner base {} on the {} strand at offset {:+} of {} cannot carry {}",
+                    strand_base,
+                    partner_strand,
+                    offset,
+                    pair.motif.as_string(),
+                    partner_mod_type.to_string()
+                );
+            }
+        }
+        Ok(pair)
+    }
+
+    /// Position of the partner base in forward motif coordinates, which may be outside the motif.
+    pub fn partner_position(&self) -> isize {
+        self.motif.position as isize + self.offset
+    }
+
+    /// Checks whether occurrences on the negative strand give the same site pairs as
+    /// those on the positive strand, so only one strand needs to be reported.
+    pub fn is_palindromic(&self) -> bool {
+        let last = self.motif.sequence.len() as isize - 1;
+        self.partner_strand == Strand::Negative
+            && self.motif.mod_type == self.partner_mod_type
+            && self.partner_position() == last - self.motif.position as isize
+            && self.motif.sequence_string() == self.motif.reverse_complement_sequence()
+    }
+}
+
+impl From<&MotifPair> for OffsetPair {
+    fn from(pair: &MotifPair) -> Self {
+        Self {
+            motif: pair.forward.clone(),
+            offset: pair.partner_position() as isize - pair.forward.position as isize,
+            partner_strand: Strand::Negative,
+            partner_mod_type: pair.reverse.mod_type,
+        }
+    }
+}
+
+impl FromStr for OffsetPair {
+    type Err = anyhow::Error;
+
+    /// Parses `MOTIF_TYPE_POS_OFFSET_STRAND_TYPE`, e.g. `ACGT_a_0_+2_-_m`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split('_').collect();
+        if parts.len() != 6 {
+            anyhow::bail!("Invalid offset pair string: {}", s);
+        }
+        let position = parts[2]
+            .parse::<u8>()
+            .map_err(|_| anyhow::anyhow!("Invalid position '{}' in offset pair: {}", parts[2], s))?;
+        let offset = parts[3]
+            .parse::<isize>()
+            .map_err(|_| anyhow::anyhow!("Invalid offset '{}' in offset pair: {}", parts[3], s))?;
+        let motif = Motif::new(parts[0], parts[1], position)?;
+        Self::new(motif, offset, parts[4].parse()?, parts[5].parse()?)
+    }
+}
+
+impl fmt::Display for OffsetPair {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}_{}_{}_{:+}_{}_{}",
+            self.motif.sequence_string(),
+            self.motif.mod_type.to_pileup_code(),
+            self.motif.position,
+            self.offset,
+            self.partner_strand,
+            self.partner_mod_type.to_pileup_code()
+        )
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -301,4 +787,272 @@ mod tests {
         let pair = MotifPair::new(forward, reverse);
         assert!(pair.is_err());
     }
+
+    #[test]
+    fn test_motif_pair_infer() {
+        let (pair, inference) = MotifPair::infer(Motif::new("GATC", "a", 1).unwrap()).unwrap();
+        assert_eq!(inference, PartnerInference::Palindrome);
+        assert!(pair.is_palindromic);
+        assert_eq!(pair.reverse.position, 1);
+
+        let (pair, inference) = MotifPair::infer(Motif::new("CCWGG", "m", 1).unwrap()).unwrap();
+        assert_eq!(inference, PartnerInference::Palindrome);
+        assert_eq!(pair.reverse.sequence_string(), "CCWGG");
+        assert_eq!(pair.reverse.position, 1);
+
+        // Type I bipartite motif with a single adenine on the complementary strand
+        let (pair, inference) =
+            MotifPair::infer(Motif::new("GAGNNNNNNNTAC", "a", 1).unwrap()).unwrap();
+        assert_eq!(inference, PartnerInference::UniqueCandidate);
+        assert_eq!(pair.reverse.sequence_string(), "GTANNNNNNNCTC");
+        assert_eq!(pair.reverse.position, 2);
+        assert!(!pair.is_palindromic);
+
+        // No candidate on the complementary strand
+        assert!(MotifPair::infer(Motif::new("AAC", "a", 0).unwrap()).is_err());
+        // Several candidates on the complementary strand
+        assert!(MotifPair::infer(Motif::new("CCGGT", "m", 0).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_expand_motif_sequence() {
+        assert_eq!(expand_motif_sequence("GATC").unwrap(), "GATC");
+        assert_eq!(expand_motif_sequence("gatc").unwrap(), "GATC");
+        assert_eq!(expand_motif_sequence("GAGN{7}TAC").unwrap(), "GAGNNNNNNNTAC");
+        assert_eq!(expand_motif_sequence("CCAN(7)GTGG").unwrap(), "CCANNNNNNNGTGG");
+        assert_eq!(expand_motif_sequence("CCA(N7)GTGG").unwrap(), "CCANNNNNNNGTGG");
+        assert_eq!(expand_motif_sequence("N{2}GATCN(1)").unwrap(), "NNGATCN");
+        assert_eq!(expand_motif_sequence("GAGN{10}").unwrap(), "GAGNNNNNNNNNN");
+
+        assert!(expand_motif_sequence("GAGN{7TAC").is_err());
+        assert!(expand_motif_sequence("GAGN{}TAC").is_err());
+        assert!(expand_motif_sequence("GAGN{0}TAC").is_err());
+        assert!(expand_motif_sequence("GAGN{x}TAC").is_err());
+        assert!(expand_motif_sequence("(7)TAC").is_err());
+        assert!(expand_motif_sequence("GA(G)TC").is_err());
+        assert!(expand_motif_sequence("{7}TAC").is_err());
+        assert!(expand_motif_sequence("GA)TC").is_err());
+
+        assert_eq!(expand_motif_sequence("GN{62}C").unwrap().len(), 64);
+        assert!(expand_motif_sequence("GN{63}C").is_err());
+        assert!(expand_motif_sequence("N{300}").is_err());
+        assert!(expand_motif_sequence("N{18446744073709551615}").is_err());
+        assert!(Motif::new(&"A".repeat(65), "a", 0).is_err());
+    }
+
+    #[test]
+    fn test_new_with_gap_notation() {
+        let motif = Motif::new("CCAN(7)GTGG", "a", 2).unwrap();
+        assert_eq!(motif.sequence_string(), "CCANNNNNNNGTGG");
+        assert_eq!(motif.as_string(), "CCANNNNNNNGTGG_6mA_2");
+        assert_eq!(motif, Motif::new("CCANNNNNNNGTGG", "a", 2).unwrap());
+
+        // Positions refer to the expanded sequence
+        let motif = Motif::new("GAGN{7}TAC", "a", 11).unwrap();
+        assert_eq!(motif.sequence[11], IupacBase::A);
+        assert!(Motif::new("GAGN{7}TAC", "a", 5).is_err());
+    }
+
+    #[test]
+    fn test_motif_contains() {
+        let gatc = Motif::new("GATC", "a", 1).unwrap();
+        assert!(gatc.contains(&gatc));
+        assert!(gatc.contains(&Motif::new("RGATCY", "a", 2).unwrap()));
+        assert!(gatc.contains(&Motif::new("GATCA", "a", 1).unwrap()));
+        assert!(!gatc.contains(&Motif::new("GANTC", "a", 1).unwrap()));
+        assert!(!Motif::new("GANTC", "a", 1).unwrap().contains(&gatc));
+        assert!(!Motif::new("RGATCY", "a", 2).unwrap().contains(&gatc));
+        // Same sequence, different modified base
+        assert!(!gatc.contains(&Motif::new("GATC", "m", 3).unwrap()));
+        assert!(!gatc.contains(&Motif::new("AGATC", "a", 0).unwrap()));
+
+        let ccwgg = Motif::new("CCWGG", "m", 1).unwrap();
+        assert!(ccwgg.contains(&Motif::new("CCAGG", "m", 1).unwrap()));
+        assert!(!Motif::new("CCAGG", "m", 1).unwrap().contains(&ccwgg));
+    }
+
+    #[test]
+    fn test_motif_overlaps_with() {
+        let gatc = Motif::new("GATC", "a", 1).unwrap();
+        assert!(gatc.overlaps_with(&Motif::new("RGATCY", "a", 2).unwrap()));
+        assert!(gatc.overlaps_with(&Motif::new("GAYNNNNNNRTC", "a", 1).unwrap()));
+        assert!(!gatc.overlaps_with(&Motif::new("GANTC", "a", 1).unwrap()));
+        assert!(!gatc.overlaps_with(&Motif::new("GATC", "m", 3).unwrap()));
+        // Overlap only outside of the other motif
+        assert!(gatc.overlaps_with(&Motif::new("ATCG", "a", 0).unwrap()));
+        assert!(!gatc.overlaps_with(&Motif::new("ATGG", "a", 0).unwrap()));
+
+        let ccwgg = Motif::new("CCWGG", "m", 1).unwrap();
+        assert!(ccwgg.overlaps_with(&Motif::new("CCNGG", "m", 1).unwrap()));
+        assert!(!ccwgg.overlaps_with(&Motif::new("CCSGG", "m", 1).unwrap()));
+    }
+
+    #[test]
+    fn test_motif_expand_variants() {
+        let motif = Motif::new("CCWGG", "m", 1).unwrap();
+        assert_eq!(motif.degeneracy(), 2);
+        assert_eq!(motif.expand_variants(), vec!["CCAGG", "CCTGG"]);
+
+        let motif = Motif::new("GANTC", "a", 1).unwrap();
+        assert_eq!(motif.degeneracy(), 4);
+        assert_eq!(Motif::new("CN{40}", "m", 0).unwrap().degeneracy(), u64::MAX);
+        assert_eq!(
+            motif.expand_variants(),
+            vec!["GAATC", "GACTC", "GAGTC", "GATTC"]
+        );
+
+        let motif = Motif::new("GATC", "a", 1).unwrap();
+        assert_eq!(motif.expand_variants(), vec!["GATC"]);
+
+        let motif = Motif::new("RGATCY", "a", 2).unwrap();
+        assert_eq!(motif.expand_variants().len(), 4);
+        assert!(motif
+            .expand_variants()
+            .iter()
+            .all(|v| Motif::new(v, "a", 2).map(|m| motif.contains(&m)).unwrap()));
+    }
+
+    #[test]
+    fn test_motif_pair_overlap_with() {
+        let gatc = MotifPair::infer(Motif::new("GATC", "a", 1).unwrap()).unwrap().0;
+        let rgatcy = MotifPair::infer(Motif::new("RGATCY", "a", 2).unwrap()).unwrap().0;
+        let gantc = MotifPair::infer(Motif::new("GANTC", "a", 1).unwrap()).unwrap().0;
+        assert_eq!(gatc.overlap_with(&rgatcy), Some(MotifOverlap::Subsumes));
+        assert_eq!(rgatcy.overlap_with(&gatc), Some(MotifOverlap::SubsumedBy));
+        assert_eq!(gatc.overlap_with(&gantc), None);
+
+        // Partial overlap at the same adenines
+        let gatcn = MotifPair::new(
+            Motif::new("GATCS", "a", 1).unwrap(),
+            Motif::new("SGATC", "a", 2).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(gatcn.overlap_with(&rgatcy), Some(MotifOverlap::Overlaps));
+
+        // The other pair found from the opposite strand
+        let forward = MotifPair::new(
+            Motif::new("GATCA", "a", 1).unwrap(),
+            Motif::new("TGATC", "a", 2).unwrap(),
+        )
+        .unwrap();
+        let reverse = MotifPair::new(
+            Motif::new("TGATC", "a", 2).unwrap(),
+            Motif::new("GATCA", "a", 1).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(forward.overlap_with(&reverse), Some(MotifOverlap::Subsumes));
+        assert_eq!(gatc.overlap_with(&forward), Some(MotifOverlap::Subsumes));
+    }
+
+    #[test]
+    fn test_match_probability() {
+        let gatc = Motif::new("GATC", "a", 1).unwrap();
+        let rgatcy = Motif::new("RGATCY", "a", 2).unwrap();
+        assert_eq!(gatc.match_probability(), 1.0 / 256.0);
+        assert_eq!(rgatcy.match_probability(), 1.0 / 1024.0);
+        assert!(rgatcy.match_probability() < gatc.match_probability());
+    }
+
+    #[test]
+    fn test_motif_pair_from_str() {
+        let pair: MotifPair = "GATC_a_1_a_2".parse().unwrap();
+        assert_eq!(pair.forward, Motif::new("GATC", "a", 1).unwrap());
+        assert_eq!(pair.reverse, Motif::new("GATC", "a", 1).unwrap());
+        assert!(pair.is_palindromic);
+        assert_eq!(pair, "GATC_a_1".parse().unwrap());
+        assert_eq!(pair, "GATC_6mA_1_6mA_2".parse().unwrap());
+
+        let (pair, inference) = MotifPair::parse("GATC_a_1").unwrap();
+        assert_eq!(inference, Some(PartnerInference::Palindrome));
+        assert_eq!(pair.to_string(), "GATC_a_1_a_2");
+        assert_eq!(MotifPair::parse("GATC_a_1_a_2").unwrap().1, None);
+
+        assert!("GATC_a_1_a".parse::<MotifPair>().is_err());
+        assert!("GATC_a_1_a_1".parse::<MotifPair>().is_err());
+        assert!("GATC_a_1_a_4".parse::<MotifPair>().is_err());
+        assert!("GATC_a_x_a_2".parse::<MotifPair>().is_err());
+        assert!("GATC".parse::<MotifPair>().is_err());
+    }
+
+    #[test]
+    fn test_motif_pair_display_round_trip() {
+        for spec in [
+            "GATC_a_1_a_2",
+            "CCWGG_m_1_m_3",
+            "CCWGG_21839_0_m_3",
+            "GATCA_a_1_a_2",
+            "ACGT_a_0_m_2",
+            "GAGNNNNNNNTAC_a_1_a_10",
+        ] {
+            let pair: MotifPair = spec.parse().unwrap();
+            assert_eq!(pair.to_string(), spec);
+            assert_eq!(pair.to_string().parse::<MotifPair>().unwrap(), pair);
+        }
+        // Canonical form expands gaps and uses pileup codes
+        let pair: MotifPair = "GAGN{7}TAC_6mA_1".parse().unwrap();
+        assert_eq!(pair.to_string(), "GAGNNNNNNNTAC_a_1_a_10");
+    }
+
+    #[test]
+    fn test_motif_pair_canonical() {
+        // The same pair written from either strand
+        let pair_1: MotifPair = "TGATC_a_2_a_3".parse().unwrap();
+        let pair_2: MotifPair = "GATCA_a_1_a_2".parse().unwrap();
+        assert_ne!(pair_1, pair_2);
+        assert_eq!(pair_1.to_string(), "TGATC_a_2_a_3");
+        assert_eq!(pair_1.clone().canonical(), pair_2.clone().canonical());
+        assert_eq!(pair_1.canonical().to_string(), "GATCA_a_1_a_2");
+
+        let pair_1: MotifPair = "CCWGG_m_1_21839_4".parse().unwrap();
+        let pair_2: MotifPair = "CCWGG_21839_0_m_3".parse().unwrap();
+        assert_eq!(pair_1.canonical(), pair_2.canonical());
+
+        let pair_1: MotifPair = "GTANNNNNNNCTC_a_2_a_11".parse().unwrap();
+        let pair_2: MotifPair = "GAGN{7}TAC_a_1".parse().unwrap();
+        assert_eq!(pair_1.canonical(), pair_2.canonical());
+
+        // Different partners are not equal
+        let pair_1: MotifPair = "CCWGG_m_1_m_3".parse().unwrap();
+        let pair_2: MotifPair = "CCWGG_m_1_m_4".parse().unwrap();
+        assert_ne!(pair_1.canonical(), pair_2.canonical());
+    }
+
+    #[test]
+    fn test_offset_pair_from_str() {
+        let pair: OffsetPair = "ACGT_a_0_+2_-_m".parse().unwrap();
+        assert_eq!(pair.offset, 2);
+        assert_eq!(pair.partner_strand, Strand::Negative);
+        assert_eq!(pair.partner_mod_type, ModType::FiveMC);
+        assert_eq!(pair.partner_position(), 2);
+        assert_eq!(pair.to_string(), "ACGT_a_0_+2_-_m");
+
+        // Partners outside the motif are not checked
+        let pair: OffsetPair = "GATC_6mA_1_-5_+_m".parse().unwrap();
+        assert_eq!(pair.partner_position(), -4);
+        assert_eq!(pair.to_string(), "GATC_a_1_-5_+_m");
+
+        // Partner base inside the motif must fit the mod type
+        assert!("ACGT_a_0_+1_-_m".parse::<OffsetPair>().is_err());
+        assert!("ACGT_a_0_+0_+_m".parse::<OffsetPair>().is_err());
+        assert!("ACGT_a_0_x_-_m".parse::<OffsetPair>().is_err());
+        assert!("ACGT_a_0_-_m".parse::<OffsetPair>().is_err());
+    }
+
+    #[test]
+    fn test_offset_pair_from_motif_pair() {
+        for (spec, palindromic) in [
+            ("GATC_a_1_a_2", true),
+            ("CCWGG_m_1_m_3", true),
+            ("CCWGG_21839_0_m_3", false),
+            ("GATCA_a_1_a_2", false),
+        ] {
+            let motif_pair: MotifPair = spec.parse().unwrap();
+            let pair = OffsetPair::from(&motif_pair);
+            assert_eq!(pair.partner_position(), motif_pair.partner_position() as isize);
+            assert_eq!(pair.is_palindromic(), palindromic);
+            assert_eq!(pair.is_palindromic(), motif_pair.is_palindromic);
+        }
+        let pair: OffsetPair = "GATC_a_1_+1_-_a".parse().unwrap();
+        assert_eq!(pair, OffsetPair::from(&"GATC_a_1_a_2".parse::<MotifPair>().unwrap()));
+    }
 }
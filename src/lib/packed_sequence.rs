@@ -0,0 +1,391 @@
+use crate::iupac::IupacBase;
+use std::fmt::Display;
+
+/// Encodes an ASCII base as the 4-bit IUPAC mask it is matched as, see
+/// [`IupacBase::to_mask`].
+///
+/// Lowercase (soft-masked) bases and characters that are not IUPAC codes, such as gaps,
+/// are encoded as `N`, so that they are only matched by `N` in a motif.
+pub fn encode_base(base: u8) -> u8 {
+    IupacBase::from_byte(base).unwrap_or(IupacBase::N).to_mask()
+}
+
+/// Complements a 4-bit IUPAC mask by swapping the A/T and C/G bits.
+pub fn complement_code(code: u8) -> u8 {
+    code.reverse_bits() >> 4
+}
+
+/// Run of equal bases, or of soft-masked bases, in a [`PackedSequence`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Run {
+    start: usize,
+    len: usize,
+    /// 4-bit IUPAC mask of the bases, unused for soft-masked runs.
+    code: u8,
+}
+
+impl Run {
+    fn end(&self) -> usize {
+        self.start + self.len
+    }
+}
+
+/// Nucleotide sequence stored as 2-bit A, C, G and T codes, four bases per byte.
+///
+/// Other IUPAC codes, mostly runs of `N`, and soft-masked (lowercase) regions are kept
+/// as sorted lists of runs, so a reference takes about a quarter of the memory of an
+/// ASCII `String`. A sequence with many isolated ambiguity codes, e.g. a consensus of a
+/// mixed population, needs a run per code and can take more.
+///
+/// Bases are read as 4-bit IUPAC masks, see [`IupacBase::to_mask`]. Soft-masked bases
+/// keep their reference base and are only treated as `N` when matching motifs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PackedSequence {
+    /// 2-bit codes with A=0, C=1, G=2, T=3. Bases in ambiguous runs are stored as A.
+    data: Vec<u8>,
+    ambiguous: Vec<Run>,
+    soft_masked: Vec<Run>,
+    len: usize,
+}
+
+impl PackedSequence {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            data: Vec::with_capacity(capacity.div_ceil(4)),
+            ..Self::default()
+        }
+    }
+
+    pub fn from_bytes(sequence: &[u8]) -> Self {
+        let mut packed = Self::with_capacity(sequence.len());
+        packed.extend_from_bytes(sequence);
+        packed
+    }
+
+    /// Appends ASCII bases, e.g. one line of a FASTA record.
+    ///
+    /// Lowercase bases are stored as their uppercase base and marked as soft-masked.
+    /// Characters that are not IUPAC codes, such as gaps, are stored as `N`.
+    pub fn extend_from_bytes(&mut self, sequence: &[u8]) {
+        self.data.reserve((self.len + sequence.len()).div_ceil(4) - self.data.len());
+        for &base in sequence {
+            if base.is_ascii_lowercase() {
+                push_run(&mut self.soft_masked, self.len, 0);
+            }
+            let code = IupacBase::from_byte(base.to_ascii_uppercase())
+                .unwrap_or(IupacBase::N)
+                .to_mask();
+            self.push_code(code);
+        }
+    }
+
+    pub fn push(&mut self, base: IupacBase) {
+        self.push_code(base.to_mask());
+    }
+
+    fn push_code(&mut self, code: u8) {
+        let bits = if code.count_ones() == 1 {
+            code.trailing_zeros() as u8
+        } else {
+            push_run(&mut self.ambiguous, self.len, code);
+            0
+        };
+        if self.len.is_multiple_of(4) {
+            self.data.push(bits);
+        } else {
+            self.data[self.len / 4] |= bits << ((self.len % 4) * 2);
+        }
+        self.len += 1;
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The 4-bit IUPAC mask of the base at `index`.
+    pub fn code(&self, index: usize) -> Option<u8> {
+        if index >= self.len {
+            return None;
+        }
+        match run_at(&self.ambiguous, index) {
+            Some(run) => Some(run.code),
+            None => Some(1 << ((self.data[index / 4] >> ((index % 4) * 2)) & 0b11)),
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<IupacBase> {
+        self.code(index).and_then(IupacBase::from_mask)
+    }
+
+    pub fn is_soft_masked(&self, index: usize) -> bool {
+        run_at(&self.soft_masked, index).is_some()
+    }
+
+    /// Iterates over the 4-bit IUPAC masks of the sequence.
+    pub fn codes(&self) -> Codes<'_> {
+        Codes {
+            sequence: self,
+            index: 0,
+            ambiguous: 0,
+            soft_masked: None,
+        }
+    }
+
+    /// Iterates over the 4-bit masks bases are matched as, with soft-masked bases as `N`.
+    pub fn match_codes(&self) -> Codes<'_> {
+        Codes {
+            soft_masked: Some(0),
+            ..self.codes()
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = IupacBase> + '_ {
+        self.codes().map(|code| IupacBase::from_mask(code).unwrap_or(IupacBase::N))
+    }
+
+    /// Checks whether `motif` matches the sequence starting at `start`.
+    ///
+    /// A motif base matches an unambiguous sequence base it contains. Ambiguous and
+    /// soft-masked sequence bases are only matched by an `N` in the motif.
+    pub fn matches_at(&self, start: usize, motif: &[IupacBase]) -> bool {
+        if start + motif.len() > self.len {
+            return false;
+        }
+        motif.iter().enumerate().all(|(i, base)| {
+            let code = if self.is_soft_masked(start + i) {
+                IupacBase::N.to_mask()
+            } else {
+                self.code(start + i).unwrap()
+            };
+            base_accepts(base, code)
+        })
+    }
+
+    /// View of the reverse complement strand without copying the sequence.
+    pub fn reverse_complement(&self) -> ReverseComplementView<'_> {
+        ReverseComplementView { sequence: self }
+    }
+
+    /// Decodes the bases in `start..end` as an uppercase string.
+    pub fn subsequence(&self, start: usize, end: usize) -> String {
+        (start..end.min(self.len))
+            .filter_map(|i| self.get(i))
+            .map(|base| base.to_string())
+            .collect()
+    }
+}
+
+/// Appends `index` to the last run if it continues it with the same code, or starts a
+/// new run.
+fn push_run(runs: &mut Vec<Run>, index: usize, code: u8) {
+    match runs.last_mut() {
+        Some(run) if run.end() == index && run.code == code => run.len += 1,
+        _ => runs.push(Run {
+            start: index,
+            len: 1,
+            code,
+        }),
+    }
+}
+
+/// The run of sorted `runs` containing `index`, if any.
+fn run_at(runs: &[Run], index: usize) -> Option<&Run> {
+    let i = runs.partition_point(|run| run.start <= index);
+    runs[..i].last().filter(|run| index < run.end())
+}
+
+/// Iterator over the 4-bit masks of a [`PackedSequence`], see [`PackedSequence::codes`].
+///
+/// Walks the runs alongside the bases instead of looking up every base.
+#[derive(Debug, Clone)]
+pub struct Codes<'a> {
+    sequence: &'a PackedSequence,
+    index: usize,
+    /// First ambiguous run not ending before `index`.
+    ambiguous: usize,
+    /// First soft-masked run not ending before `index`, `None` to read the real bases.
+    soft_masked: Option<usize>,
+}
+
+impl Iterator for Codes<'_> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        let sequence = self.sequence;
+        let index = self.index;
+        if index >= sequence.len {
+            return None;
+        }
+        self.index += 1;
+        if let Some(run_idx) = self.soft_masked.as_mut() {
+            while sequence.soft_masked.get(*run_idx).is_some_and(|run| run.end() <= index) {
+                *run_idx += 1;
+            }
+            if sequence.soft_masked.get(*run_idx).is_some_and(|run| run.start <= index) {
+                return Some(IupacBase::N.to_mask());
+            }
+        }
+        while sequence.ambiguous.get(self.ambiguous).is_some_and(|run| run.end() <= index) {
+            self.ambiguous += 1;
+        }
+        match sequence.ambiguous.get(self.ambiguous) {
+            Some(run) if run.start <= index => Some(run.code),
+            _ => Some(1 << ((sequence.data[index / 4] >> ((index % 4) * 2)) & 0b11)),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.sequence.len - self.index;
+        (remaining, Some(remaining))
+    }
+}
+
+impl Display for PackedSequence {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for base in self.iter() {
+            write!(f, "{}", base)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reverse complement of a [`PackedSequence`], indexed from the 5' end of the negative strand.
+#[derive(Debug, Clone, Copy)]
+pub struct ReverseComplementView<'a> {
+    sequence: &'a PackedSequence,
+}
+
+impl ReverseComplementView<'_> {
+    pub fn len(&self) -> usize {
+        self.sequence.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sequence.is_empty()
+    }
+
+    pub fn code(&self, index: usize) -> Option<u8> {
+        if index >= self.len() {
+            return None;
+        }
+        self.sequence
+            .code(self.len() - 1 - index)
+            .map(complement_code)
+    }
+
+    pub fn get(&self, index: usize) -> Option<IupacBase> {
+        self.code(index).and_then(IupacBase::from_mask)
+    }
+
+    /// Position on the positive strand of `index` in the view.
+    pub fn forward_position(&self, index: usize) -> usize {
+        self.len() - 1 - index
+    }
+}
+
+impl Display for ReverseComplementView<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for i in 0..self.len() {
+            write!(f, "{}", self.get(i).unwrap_or(IupacBase::N))?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether a motif base accepts a sequence base given as 4-bit mask.
+pub fn base_accepts(base: &IupacBase, code: u8) -> bool {
+    match base {
+        IupacBase::N => true,
+        _ => code.count_ones() == 1 && base.to_mask() & code != 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pack_round_trip() {
+        let sequence = "ACGTRYSWKMBDHVN";
+        let packed = PackedSequence::from_bytes(sequence.as_bytes());
+        assert_eq!(packed.len(), 15);
+        assert_eq!(packed.to_string(), sequence);
+        assert_eq!(packed.get(4), Some(IupacBase::R));
+        assert_eq!(packed.get(15), None);
+    }
+
+    #[test]
+    fn test_pack_soft_masked_and_invalid() {
+        let packed = PackedSequence::from_bytes(b"acgT-*");
+        assert_eq!(packed.to_string(), "ACGTNN");
+        assert!(packed.is_soft_masked(2));
+        assert!(!packed.is_soft_masked(3));
+        assert!(!packed.is_soft_masked(4));
+        let match_codes: Vec<u8> = packed.match_codes().collect();
+        assert_eq!(match_codes, vec![0b1111, 0b1111, 0b1111, 0b1000, 0b1111, 0b1111]);
+    }
+
+    #[test]
+    fn test_pack_runs() {
+        let packed = PackedSequence::from_bytes(b"NNNNACGTnnRRacgtYN");
+        assert_eq!(packed.to_string(), "NNNNACGTNNRRACGTYN");
+        assert_eq!(packed.ambiguous.len(), 5);
+        assert_eq!(packed.soft_masked.len(), 2);
+        assert_eq!(packed.data.len(), 5);
+        let codes: Vec<u8> = packed.codes().collect();
+        let looked_up: Vec<u8> = (0..packed.len()).map(|i| packed.code(i).unwrap()).collect();
+        assert_eq!(codes, looked_up);
+    }
+
+    #[test]
+    fn test_extend_from_bytes() {
+        let mut packed = PackedSequence::new();
+        packed.extend_from_bytes(b"ACG");
+        packed.extend_from_bytes(b"TTA");
+        packed.push(IupacBase::G);
+        assert_eq!(packed.to_string(), "ACGTTAG");
+        assert_eq!(packed.codes().count(), 7);
+    }
+
+    #[test]
+    fn test_reverse_complement_view() {
+        let packed = PackedSequence::from_bytes(b"AACGTN");
+        let revcomp = packed.reverse_complement();
+        assert_eq!(revcomp.to_string(), "NACGTT");
+        assert_eq!(revcomp.get(1), Some(IupacBase::A));
+        assert_eq!(revcomp.forward_position(1), 4);
+        assert_eq!(revcomp.get(6), None);
+    }
+
+    #[test]
+    fn test_matches_at() {
+        let packed = PackedSequence::from_bytes(b"GAATCGANTCgaatc");
+        let motif = [
+            IupacBase::G,
+            IupacBase::A,
+            IupacBase::N,
+            IupacBase::T,
+            IupacBase::C,
+        ];
+        assert!(packed.matches_at(0, &motif));
+        assert!(packed.matches_at(5, &motif));
+        assert!(!packed.matches_at(1, &motif));
+        assert!(!packed.matches_at(6, &motif));
+        // Soft-masked bases are only matched by N
+        assert!(!packed.matches_at(10, &motif));
+        assert!(packed.matches_at(10, &[IupacBase::N; 5]));
+
+        // Ambiguous sequence bases are only matched by N
+        let motif = [IupacBase::G, IupacBase::A, IupacBase::W];
+        assert!(packed.matches_at(0, &motif));
+        assert!(!packed.matches_at(5, &motif));
+    }
+}
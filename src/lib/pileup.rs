@@ -2,7 +2,6 @@ use anyhow::anyhow;
 use anyhow::Result;
 use atoi;
 use csv::{ByteRecord, ReaderBuilder};
-use log::{debug, info, warn};
 use crate::{
     modtype::ModType, 
     strand::Strand
@@ -282,6 +281,7 @@ mod tests {
     ///   18) '.'
     ///
     /// Note: Adjust the placeholders or column order to match your actual parsing logic.
+    #[allow(clippy::too_many_arguments)]
     fn create_pileup_line(
         reference: &str,
         position: usize,
@@ -0,0 +1,72 @@
+/// SplitMix64 pseudo-random number generator, small and fast enough for resampling.
+///
+/// Not suitable for cryptography. The same seed always gives the same sequence.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// Generator for the stream named `key` of a run with `seed`, so that results do not
+    /// depend on the order in which streams are used.
+    pub fn from_key(seed: u64, key: &str) -> Self {
+        // FNV-1a, stable across runs and platforms unlike the std and ahash hashers
+        let hash = key.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+        });
+        Self::new(seed ^ hash)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform value in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniform index in `0..n`.
+    pub fn below(&mut self, n: usize) -> usize {
+        ((self.next_u64() as u128 * n as u128) >> 64) as usize
+    }
+
+    /// Number of successes in `n` trials with success probability `p`.
+    pub fn binomial(&mut self, n: u32, p: f64) -> u32 {
+        (0..n).filter(|_| self.next_f64() < p).count() as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_mix() {
+        // First outputs for seed 1234567 of the reference implementation
+        let mut rng = SplitMix64::new(1_234_567);
+        assert_eq!(rng.next_u64(), 6_457_827_717_110_365_317);
+        assert_eq!(rng.next_u64(), 3_203_168_211_198_807_973);
+
+        let mut rng = SplitMix64::from_key(1, "contig_1");
+        assert_eq!(rng, SplitMix64::from_key(1, "contig_1"));
+        assert_ne!(rng, SplitMix64::from_key(1, "contig_2"));
+        for _ in 0..1000 {
+            let x = rng.next_f64();
+            assert!((0.0..1.0).contains(&x));
+            assert!(rng.below(7) < 7);
+        }
+        assert_eq!(rng.binomial(20, 0.0), 0);
+        assert_eq!(rng.binomial(20, 1.0), 20);
+        let n_success: u32 = (0..100).map(|_| rng.binomial(100, 0.3)).sum();
+        assert!((2_700..3_300).contains(&n_success));
+    }
+}
@@ -0,0 +1,380 @@
+use crate::{
+    iupac::IupacBase,
+    motif::Motif,
+    packed_sequence::{base_accepts, encode_base, PackedSequence},
+};
+use anyhow::{bail, Result};
+
+/// Number of pattern positions packed into one bit-parallel state word.
+const WORD_BITS: usize = 64;
+
+/// Sites of one motif in a sequence, given as positions of the modified base.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MotifSites {
+    /// Modified positions of motif occurrences on the positive strand.
+    pub forward: Vec<usize>,
+    /// Modified positions of reverse complement occurrences, i.e. the motif on the negative strand.
+    pub reverse: Vec<usize>,
+}
+
+/// A pattern placed in one of the state words of the scanner.
+#[derive(Debug, Clone)]
+struct Pattern {
+    word: usize,
+    end_bit: u64,
+    len: usize,
+}
+
+/// Group of patterns sharing one 64 bit Shift-And state.
+#[derive(Debug, Clone)]
+struct PatternWord {
+    used_bits: usize,
+    start_bits: u64,
+    end_bits: u64,
+    /// Bits of pattern positions accepting a sequence base, indexed by its 4-bit code.
+    masks: [u64; 16],
+}
+
+/// Matches a set of IUPAC motifs and their reverse complements in a single pass.
+///
+/// Motifs are compiled into bit-parallel Shift-And automata over a 4-bit base
+/// encoding, see [`IupacBase::to_mask`]. All patterns are packed into as few 64 bit
+/// words as possible, and every word is advanced for each base, so the sequence is
+/// only read once regardless of the number of motifs. Overlapping occurrences are all
+/// reported, and ambiguous or soft-masked bases in the sequence are only matched by an
+/// `N` in the motif.
+#[derive(Debug, Clone)]
+pub struct MotifScanner {
+    words: Vec<PatternWord>,
+    patterns: Vec<Pattern>,
+    /// Pattern index and modified base offset for the forward and reverse complement of each motif.
+    motifs: Vec<((usize, usize), (usize, usize))>,
+}
+
+impl MotifScanner {
+    pub fn new<'a, I>(motifs: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = &'a Motif>,
+    {
+        let mut scanner = Self {
+            words: Vec::new(),
+            patterns: Vec::new(),
+            motifs: Vec::new(),
+        };
+        let mut sequences: Vec<Vec<IupacBase>> = Vec::new();
+        for motif in motifs {
+            let complement = motif.reverse_complement()?;
+            let forward = scanner.add_pattern(&mut sequences, &motif.sequence)?;
+            let reverse = scanner.add_pattern(&mut sequences, &complement.sequence)?;
+            scanner.motifs.push((
+                (forward, motif.position as usize),
+                (reverse, complement.position as usize),
+            ));
+        }
+        Ok(scanner)
+    }
+
+    /// Creates a scanner reporting the start of each occurrence instead of a modified base.
+    ///
+    /// The reverse sites are the leftmost positions of reverse complement matches on the
+    /// positive strand.
+    pub fn from_sequences<'a, I>(sequences: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = &'a [IupacBase]>,
+    {
+        let mut scanner = Self {
+            words: Vec::new(),
+            patterns: Vec::new(),
+            motifs: Vec::new(),
+        };
+        let mut registered: Vec<Vec<IupacBase>> = Vec::new();
+        for sequence in sequences {
+            let complement: Vec<IupacBase> =
+                sequence.iter().rev().map(|base| base.complement()).collect();
+            let forward = scanner.add_pattern(&mut registered, sequence)?;
+            let reverse = scanner.add_pattern(&mut registered, &complement)?;
+            scanner.motifs.push(((forward, 0), (reverse, 0)));
+        }
+        Ok(scanner)
+    }
+
+    /// Registers a pattern, reusing an identical one such as the reverse complement of a palindrome.
+    fn add_pattern(
+        &mut self,
+        sequences: &mut Vec<Vec<IupacBase>>,
+        sequence: &[IupacBase],
+    ) -> Result<usize> {
+        if let Some(idx) = sequences.iter().position(|s| s == sequence) {
+            return Ok(idx);
+        }
+        let len = sequence.len();
+        if len == 0 || len > WORD_BITS {
+            bail!(
+                "Motifs must be between 1 and {} bases long to be scanned, got {}",
+                WORD_BITS,
+                len
+            );
+        }
+        let offset = match self.words.last() {
+            Some(word) if word.used_bits + len <= WORD_BITS => word.used_bits,
+            _ => {
+                self.words.push(PatternWord {
+                    used_bits: 0,
+                    start_bits: 0,
+                    end_bits: 0,
+                    masks: [0; 16],
+                });
+                0
+            }
+        };
+        let word_idx = self.words.len() - 1;
+        let word = &mut self.words[word_idx];
+        word.used_bits = offset + len;
+        word.start_bits |= 1 << offset;
+        let end_bit = 1 << (offset + len - 1);
+        word.end_bits |= end_bit;
+        for (i, base) in sequence.iter().enumerate() {
+            let bit = 1 << (offset + i);
+            for (code, mask) in word.masks.iter_mut().enumerate() {
+                if base_accepts(base, code as u8) {
+                    *mask |= bit;
+                }
+            }
+        }
+        self.patterns.push(Pattern {
+            word: word_idx,
+            end_bit,
+            len,
+        });
+        sequences.push(sequence.to_vec());
+        Ok(self.patterns.len() - 1)
+    }
+
+    /// Number of motifs the scanner was built from.
+    pub fn len(&self) -> usize {
+        self.motifs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.motifs.is_empty()
+    }
+
+    /// Finds the sites of all motifs in one pass over an ASCII `sequence`.
+    ///
+    /// The returned vector is in the same order as the motifs given to [`MotifScanner::new`].
+    pub fn scan(&self, sequence: &[u8]) -> Vec<MotifSites> {
+        let hits = self.scan_codes(sequence.iter().map(|&base| encode_base(base)));
+        self.collect_sites(&hits, None)
+    }
+
+    /// Finds the sites of all motifs in one pass over a packed sequence.
+    pub fn scan_packed(&self, sequence: &PackedSequence) -> Vec<MotifSites> {
+        let hits = self.scan_codes(sequence.match_codes());
+        self.collect_sites(&hits, None)
+    }
+
+    /// Finds the sites of all motifs in a circular sequence, including occurrences
+    /// spanning the end and start of the sequence.
+    ///
+    /// Sites of wrapping occurrences are reported modulo the sequence length.
+    pub fn scan_packed_circular(&self, sequence: &PackedSequence) -> Vec<MotifSites> {
+        let len = sequence.len();
+        let max_pattern_len = self.patterns.iter().map(|p| p.len).max().unwrap_or(1);
+        let codes = sequence.match_codes().cycle().take(len + max_pattern_len - 1);
+        let hits = self.scan_codes(codes);
+        self.collect_sites(&hits, Some(len))
+    }
+
+    /// Runs the automata over `codes`, returning the start of every match per pattern.
+    fn scan_codes(&self, codes: impl Iterator<Item = u8>) -> Vec<Vec<usize>> {
+        let mut states = vec![0u64; self.words.len()];
+        let mut hits: Vec<Vec<usize>> = vec![Vec::new(); self.patterns.len()];
+        for (i, code) in codes.enumerate() {
+            let mut any_end = false;
+            for (state, word) in states.iter_mut().zip(self.words.iter()) {
+                *state = ((*state << 1) | word.start_bits) & word.masks[code as usize];
+                any_end |= *state & word.end_bits != 0;
+            }
+            if !any_end {
+                continue;
+            }
+            for (pattern, pattern_hits) in self.patterns.iter().zip(hits.iter_mut()) {
+                if states[pattern.word] & pattern.end_bit != 0 {
+                    pattern_hits.push(i + 1 - pattern.len);
+                }
+            }
+        }
+        hits
+    }
+
+    /// Converts pattern match starts to modified base positions per motif.
+    ///
+    /// With `circular_len`, starts past the end of the sequence are dropped, as they
+    /// were already found at the beginning, and positions are wrapped around the origin.
+    fn collect_sites(&self, hits: &[Vec<usize>], circular_len: Option<usize>) -> Vec<MotifSites> {
+        let sites_of = |pattern: usize, offset: usize| -> Vec<usize> {
+            match circular_len {
+                Some(len) => hits[pattern]
+                    .iter()
+                    .take_while(|&&start| start < len)
+                    .map(|start| (start + offset) % len)
+                    .collect(),
+                None => hits[pattern].iter().map(|start| start + offset).collect(),
+            }
+        };
+        self.motifs
+            .iter()
+            .map(|&((fwd, fwd_offset), (rev, rev_offset))| MotifSites {
+                forward: sites_of(fwd, fwd_offset),
+                reverse: sites_of(rev, rev_offset),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::motif::MotifLike;
+    use regex::Regex;
+
+    fn regex_sites(sequence: &str, regex: &str, position: u8) -> Vec<usize> {
+        let re = Regex::new(&format!("^{}", regex)).unwrap();
+        (0..sequence.len())
+            .filter(|&i| re.is_match(&sequence[i..]))
+            .map(|i| i + position as usize)
+            .collect()
+    }
+
+    #[test]
+    fn test_scan_single_motif() {
+        let motif = Motif::new("GATC", "6mA", 1).unwrap();
+        let scanner = MotifScanner::new([&motif]).unwrap();
+        let sites = scanner.scan(b"AGATCGGATCAA");
+        assert_eq!(sites.len(), 1);
+        assert_eq!(sites[0].forward, vec![2, 7]);
+        assert_eq!(sites[0].reverse, vec![3, 8]);
+    }
+
+    #[test]
+    fn test_scan_non_palindromic_motif() {
+        let motif = Motif::new("CCGGA", "6mA", 4).unwrap();
+        let scanner = MotifScanner::new([&motif]).unwrap();
+        let sites =
+            scanner.scan(b"ACCTCCGGCCGGAGGTCGTACGCCGGATCCGGTCCGGTCCGGTACCGGACGTACCGGTCGCCGGAT");
+        assert_eq!(sites[0].forward, vec![12, 26, 48, 64]);
+        assert_eq!(sites[0].reverse, vec![3, 27, 32, 37]);
+    }
+
+    #[test]
+    fn test_scan_sequence_n() {
+        let motifs = [
+            Motif::new("GANTC", "6mA", 1).unwrap(),
+            Motif::new("GATC", "6mA", 1).unwrap(),
+        ];
+        let scanner = MotifScanner::new(motifs.iter()).unwrap();
+        let sites = scanner.scan(b"GANTCGNTC");
+        assert_eq!(sites[0].forward, vec![1]);
+        assert!(sites[1].forward.is_empty());
+    }
+
+    #[test]
+    fn test_scan_soft_masked() {
+        let motifs = [
+            Motif::new("GATC", "6mA", 1).unwrap(),
+            Motif::new("GNTC", "5mC", 3).unwrap(),
+        ];
+        let scanner = MotifScanner::new(motifs.iter()).unwrap();
+        let sites = scanner.scan(b"GATCgatcGaTC");
+        assert_eq!(sites[0].forward, vec![1]);
+        assert_eq!(sites[1].forward, vec![3, 11]);
+    }
+
+    #[test]
+    fn test_scan_too_long_motif() {
+        let sequence = vec![IupacBase::A; 65];
+        assert!(MotifScanner::from_sequences([sequence.as_slice()]).is_err());
+    }
+
+    #[test]
+    fn test_scan_matches_regex() {
+        let sequences = [
+            "GAGTCGAGTCGAATCGATCGATCGATCNNGATC",
+            "CCAGGCCTGGCCAGGCCCCTGGGGccaggAAAA",
+            "GGAGGAGGAGGAGGAGG",
+            "GCAGCTGCGGCGCNGCGCGAGNNNNNNNTACGTAGAGAAAAAAATAC",
+        ];
+        // Enough motifs to need several state words
+        let motifs = [
+            Motif::new("GANTC", "6mA", 1).unwrap(),
+            Motif::new("GATC", "6mA", 1).unwrap(),
+            Motif::new("CCWGG", "5mC", 1).unwrap(),
+            Motif::new("GGAGG", "6mA", 2).unwrap(),
+            Motif::new("GCNGC", "5mC", 1).unwrap(),
+            Motif::new("AAAA", "6mA", 3).unwrap(),
+            Motif::new("RGATCY", "6mA", 2).unwrap(),
+            Motif::new("GAGNNNNNNNTAC", "6mA", 1).unwrap(),
+            Motif::new("CAGCTG", "4mC", 3).unwrap(),
+            Motif::new("CCAGGCCTGGCCAGGCCCC", "5mC", 0).unwrap(),
+            Motif::new("GGAGGAGGAGGAGGAGG", "6mA", 2).unwrap(),
+            Motif::new("GCAGCTGCGGCGCNGCGCGAGN", "5mC", 1).unwrap(),
+        ];
+        let scanner = MotifScanner::new(motifs.iter()).unwrap();
+        assert!(scanner.words.len() > 1);
+        for sequence in sequences {
+            let sites = scanner.scan(sequence.as_bytes());
+            let packed = PackedSequence::from_bytes(sequence.as_bytes());
+            assert_eq!(scanner.scan_packed(&packed), sites);
+            for (motif, motif_sites) in motifs.iter().zip(sites.iter()) {
+                let expected = regex_sites(sequence, &motif.regex().unwrap(), motif.position);
+                assert_eq!(motif_sites.forward, expected);
+                let complement = motif.reverse_complement().unwrap();
+                let expected =
+                    regex_sites(sequence, &complement.regex().unwrap(), complement.position);
+                assert_eq!(motif_sites.reverse, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_scan_circular() {
+        let motif = Motif::new("GATC", "6mA", 1).unwrap();
+        let scanner = MotifScanner::new([&motif]).unwrap();
+        // GATC spans the origin as ...GA|TC...
+        let packed = PackedSequence::from_bytes(b"TCAAAAGATCAAAAGA");
+        let sites = scanner.scan_packed(&packed);
+        assert_eq!(sites[0].forward, vec![7]);
+        let sites = scanner.scan_packed_circular(&packed);
+        assert_eq!(sites[0].forward, vec![7, 15]);
+        assert_eq!(sites[0].reverse, vec![8, 0]);
+
+        // Modified base wraps around the origin
+        let motif = Motif::new("CCGGA", "6mA", 4).unwrap();
+        let scanner = MotifScanner::new([&motif]).unwrap();
+        let packed = PackedSequence::from_bytes(b"ATTTTTTCCGG");
+        let sites = scanner.scan_packed_circular(&packed);
+        assert_eq!(sites[0].forward, vec![0]);
+        assert_eq!(sites[0].reverse, vec![6]);
+
+        // Contigs shorter than the motif
+        let motif = Motif::new("GATCGATC", "6mA", 1).unwrap();
+        let scanner = MotifScanner::new([&motif]).unwrap();
+        let packed = PackedSequence::from_bytes(b"GATC");
+        let sites = scanner.scan_packed_circular(&packed);
+        assert_eq!(sites[0].forward, vec![1]);
+        assert!(scanner
+            .scan_packed_circular(&PackedSequence::new())[0]
+            .forward
+            .is_empty());
+    }
+
+    #[test]
+    fn test_scan_from_sequences() {
+        let motif = Motif::new("CCGGA", "6mA", 4).unwrap();
+        let scanner = MotifScanner::from_sequences([motif.sequence.as_slice()]).unwrap();
+        let sites = scanner
+            .scan(b"ACCTCCGGCCGGAGGTCGTACGCCGGATCCGGTCCGGTCCGGTACCGGACGTACCGGTCGCCGGAT");
+        assert_eq!(sites[0].forward, vec![8, 22, 44, 60]);
+        assert_eq!(sites[0].reverse, vec![3, 27, 32, 37]);
+    }
+}
@@ -0,0 +1,265 @@
+use crate::{
+    iupac::IupacBase,
+    modtype::ModType,
+    motif::{expand_motif_sequence, MotifPair},
+    strand::Strand,
+};
+use anyhow::{anyhow, bail, Result};
+use std::{fmt, str::FromStr};
+
+/// A modified base of a motif occurrence.
+///
+/// The position is given in forward motif coordinates. On the negative strand it
+/// refers to the base complementary to the forward motif base at that position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ModPosition {
+    pub strand: Strand,
+    pub position: u8,
+    pub mod_type: ModType,
+}
+
+impl fmt::Display for ModPosition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}{}{}",
+            self.mod_type.to_pileup_code(),
+            self.strand,
+            self.position
+        )
+    }
+}
+
+impl FromStr for ModPosition {
+    type Err = anyhow::Error;
+
+    /// Parses `TYPE+POS` or `TYPE-POS`, e.g. `a+1` or `21839-3`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let split = s
+            .rfind(['+', '-'])
+            .ok_or_else(|| anyhow!("Missing strand in modified position: {}", s))?;
+        let mod_type = s[..split].parse::<ModType>()?;
+        let strand = s[split..split + 1].parse::<Strand>()?;
+        let position = s[split + 1..]
+            .parse::<u8>()
+            .map_err(|_| anyhow!("Invalid position in modified position: {}", s))?;
+        Ok(Self {
+            strand,
+            position,
+            mod_type,
+        })
+    }
+}
+
+/// A motif with any number of modified bases on either strand per occurrence.
+///
+/// Generalises [`MotifPair`], which is the special case of one modified base on each
+/// strand. Written as the motif followed by its modified positions, e.g.
+/// `GAAGNNNNNNCTTC_a+1_a+2_a-11_a-12`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MotifSiteSet {
+    pub sequence: Vec<IupacBase>,
+    pub positions: Vec<ModPosition>,
+}
+
+impl MotifSiteSet {
+    pub fn new(sequence: &str, positions: Vec<ModPosition>) -> Result<Self> {
+        let sequence = expand_motif_sequence(sequence)?;
+        let parsed_sequence = sequence
+            .chars()
+            .map(IupacBase::from_char)
+            .collect::<Result<Vec<IupacBase>>>()?;
+        if positions.is_empty() {
+            bail!("No modified positions given for motif: {}", sequence);
+        }
+        for (i, position) in positions.iter().enumerate() {
+            let base = parsed_sequence.get(position.position as usize).ok_or_else(|| {
+                anyhow!(
+                    "Position {} is out of bounds for sequence: {}",
+                    position.position,
+                    sequence
+                )
+            })?;
+            let strand_base = match position.strand {
+                Strand::Positive => *base,
+                Strand::Negative => base.complement(),
+            };
+            if &strand_base != position.mod_type.get_iupac_base() {
+                bail!(
+                    "Base at position {} on the {} strand ({}) does not match mod type: {}",
+                    position.position,
+                    position.strand,
+                    strand_base,
+                    position.mod_type.to_string()
+                );
+            }
+            if positions[..i].contains(position) {
+                bail!("Duplicate modified position {} in motif: {}", position, sequence);
+            }
+        }
+        Ok(Self {
+            sequence: parsed_sequence,
+            positions,
+        })
+    }
+
+    pub fn sequence_string(&self) -> String {
+        self.sequence.iter().map(|b| b.to_string()).collect()
+    }
+
+    /// Checks whether an occurrence found on the negative strand measures the same
+    /// bases as one found on the positive strand, in which case only one needs scanning.
+    pub fn is_palindromic(&self) -> bool {
+        let reverse_sequence: Vec<IupacBase> =
+            self.sequence.iter().rev().map(|b| b.complement()).collect();
+        reverse_sequence == self.sequence
+            && self
+                .reverse_positions()
+                .iter()
+                .all(|position| self.positions.contains(position))
+    }
+
+    /// Modified positions of an occurrence on the negative strand, relative to the
+    /// leftmost base of the occurrence on the positive strand.
+    pub fn reverse_positions(&self) -> Vec<ModPosition> {
+        let last = self.sequence.len() as u8 - 1;
+        self.positions
+            .iter()
+            .map(|position| ModPosition {
+                strand: match position.strand {
+                    Strand::Positive => Strand::Negative,
+                    Strand::Negative => Strand::Positive,
+                },
+                position: last - position.position,
+                mod_type: position.mod_type,
+            })
+            .collect()
+    }
+}
+
+impl From<&MotifPair> for MotifSiteSet {
+    fn from(pair: &MotifPair) -> Self {
+        Self {
+            sequence: pair.forward.sequence.clone(),
+            positions: vec![
+                ModPosition {
+                    strand: Strand::Positive,
+                    position: pair.forward.position,
+                    mod_type: pair.forward.mod_type,
+                },
+                ModPosition {
+                    strand: Strand::Negative,
+                    position: pair.partner_position(),
+                    mod_type: pair.reverse.mod_type,
+                },
+            ],
+        }
+    }
+}
+
+impl FromStr for MotifSiteSet {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split('_');
+        let sequence = parts
+            .next()
+            .ok_or_else(|| anyhow!("Invalid motif site set: {}", s))?;
+        let positions = parts
+            .map(|part| part.parse::<ModPosition>())
+            .collect::<Result<Vec<ModPosition>>>()
+            .map_err(|e| anyhow!("Invalid motif site set: {} ({})", s, e))?;
+        Self::new(sequence, positions)
+    }
+}
+
+impl fmt::Display for MotifSiteSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.sequence_string())?;
+        for position in self.positions.iter() {
+            write!(f, "_{}", position)?;
+        }
+        Ok(())
+    }
+}
+
+/// Methylation pattern of an occurrence, with one bit per modified position in the
+/// order of [`MotifSiteSet::positions`], written as e.g. `101`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MethylationPattern(pub Vec<bool>);
+
+impl fmt::Display for MethylationPattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for &methylated in self.0.iter() {
+            write!(f, "{}", if methylated { '1' } else { '0' })?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mod_position_from_str() {
+        let position: ModPosition = "a+1".parse().unwrap();
+        assert_eq!(position.strand, Strand::Positive);
+        assert_eq!(position.position, 1);
+        assert_eq!(position.mod_type, ModType::SixMA);
+        let position: ModPosition = "21839-12".parse().unwrap();
+        assert_eq!(position.strand, Strand::Negative);
+        assert_eq!(position.position, 12);
+        assert_eq!(position.mod_type, ModType::FourMC);
+        assert_eq!(position.to_string(), "21839-12");
+
+        assert!("a1".parse::<ModPosition>().is_err());
+        assert!("a+".parse::<ModPosition>().is_err());
+        assert!("x+1".parse::<ModPosition>().is_err());
+    }
+
+    #[test]
+    fn test_site_set_from_str() {
+        let set: MotifSiteSet = "GAAGNNNNNNCTTC_a+1_a+2_a-11_a-12".parse().unwrap();
+        assert_eq!(set.positions.len(), 4);
+        assert_eq!(set.to_string(), "GAAGNNNNNNCTTC_a+1_a+2_a-11_a-12");
+        assert!(set.is_palindromic());
+
+        let set: MotifSiteSet = "GAAGN{6}CTTC_a+1_a+2".parse().unwrap();
+        assert_eq!(set.sequence_string(), "GAAGNNNNNNCTTC");
+        assert!(!set.is_palindromic());
+
+        // Base does not match mod type on the given strand
+        assert!("GATC_a-1".parse::<MotifSiteSet>().is_err());
+        assert!("GATC_a+4".parse::<MotifSiteSet>().is_err());
+        assert!("GATC_a+1_a+1".parse::<MotifSiteSet>().is_err());
+        assert!("GATC".parse::<MotifSiteSet>().is_err());
+    }
+
+    #[test]
+    fn test_site_set_reverse_positions() {
+        let set: MotifSiteSet = "GATCA_a+1_a-2_a+4".parse().unwrap();
+        assert_eq!(
+            set.reverse_positions(),
+            vec![
+                "a-3".parse().unwrap(),
+                "a+2".parse().unwrap(),
+                "a-0".parse().unwrap()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_site_set_from_pair() {
+        let pair: MotifPair = "CCWGG_21839_0_m_3".parse().unwrap();
+        let set = MotifSiteSet::from(&pair);
+        assert_eq!(set.to_string(), "CCWGG_21839+0_m-3");
+        assert_eq!(set, "CCWGG_21839+0_m-3".parse().unwrap());
+    }
+
+    #[test]
+    fn test_methylation_pattern_display() {
+        let pattern = MethylationPattern(vec![true, false, true]);
+        assert_eq!(pattern.to_string(), "101");
+    }
+}
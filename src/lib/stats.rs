@@ -0,0 +1,805 @@
+/// Lanczos approximation coefficients for g = 7, n = 9.
+const LANCZOS: [f64; 9] = [
+    0.999_999_999_999_809_9,
+    676.520_368_121_885_1,
+    -1_259.139_216_722_402_8,
+    771.323_428_777_653_1,
+    -176.615_029_162_140_6,
+    12.507_343_278_686_905,
+    -0.138_571_095_265_720_12,
+    9.984_369_578_019_572e-6,
+    1.505_632_735_149_311_6e-7,
+];
+
+/// Number of nuisance parameter values tried by [`barnard_exact`].
+const BARNARD_GRID: usize = 100;
+
+/// Golden section steps refining each maximum of [`barnard_exact`] between the grid
+/// neighbours of the best grid value, narrowing the bracket by a factor of about 15000.
+const BARNARD_REFINE_STEPS: usize = 20;
+
+/// Standard normal quantile at 0.975, for two-sided 95% confidence intervals.
+const Z_95: f64 = 1.959_963_984_540_054;
+
+/// Tail probability on each side of a 95% confidence interval.
+const TAIL_95: f64 = 0.025;
+
+/// Bisection steps when inverting a test, well below f64 precision on the searched ranges.
+const BISECTION_STEPS: usize = 100;
+
+/// Maximum number of terms of the incomplete beta continued fraction.
+const MAX_FRACTION_TERMS: usize = 300;
+
+/// Natural logarithm of the gamma function for `x > 0`.
+pub fn ln_gamma(x: f64) -> f64 {
+    if x < 0.5 {
+        // Reflection formula
+        let pi = std::f64::consts::PI;
+        return (pi / (pi * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let t = x + 7.5;
+    let sum = LANCZOS
+        .iter()
+        .enumerate()
+        .skip(1)
+        .fold(LANCZOS[0], |sum, (i, c)| sum + c / (x + i as f64));
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+/// Natural logarithm of the binomial coefficient `n` choose `k`.
+pub fn ln_choose(n: u32, k: u32) -> f64 {
+    if k > n {
+        return f64::NEG_INFINITY;
+    }
+    ln_gamma(n as f64 + 1.0) - ln_gamma(k as f64 + 1.0) - ln_gamma((n - k) as f64 + 1.0)
+}
+
+/// P-values of an exact test on a 2x2 table.
+///
+/// `greater` tests whether the first row has a higher proportion in the first column
+/// than the second row, `less` the opposite.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExactTestResult {
+    pub two_sided: f64,
+    pub greater: f64,
+    pub less: f64,
+}
+
+/// Fisher's exact test on the table `[[a, b], [c, d]]`.
+///
+/// The two-sided p-value sums all tables with the same margins that are at most as
+/// likely as the observed one.
+pub fn fisher_exact(a: u32, b: u32, c: u32, d: u32) -> ExactTestResult {
+    let (row_1, row_2, col_1) = (a + b, c + d, a + c);
+    let n = row_1 + row_2;
+    let ln_total = ln_choose(n, col_1);
+    let probability = |x: u32| {
+        (ln_choose(row_1, x) + ln_choose(row_2, col_1 - x) - ln_total).exp()
+    };
+
+    let min_x = col_1.saturating_sub(row_2);
+    let max_x = row_1.min(col_1);
+    let observed = probability(a);
+    let (mut two_sided, mut greater, mut less) = (0.0, 0.0, 0.0);
+    for x in min_x..=max_x {
+        let p = probability(x);
+        // Relative tolerance for tables as likely as the observed one
+        if p <= observed * (1.0 + 1e-7) {
+            two_sided += p;
+        }
+        if x >= a {
+            greater += p;
+        }
+        if x <= a {
+            less += p;
+        }
+    }
+    ExactTestResult {
+        two_sided: two_sided.min(1.0),
+        greater: greater.min(1.0),
+        less: less.min(1.0),
+    }
+}
+
+/// Pooled z statistic for the difference in proportions `x_1 / n_1 - x_2 / n_2`.
+fn pooled_z(x_1: u32, n_1: u32, x_2: u32, n_2: u32) -> f64 {
+    let (n_1, n_2) = (n_1 as f64, n_2 as f64);
+    let pooled = (x_1 as f64 + x_2 as f64) / (n_1 + n_2);
+    let variance = pooled * (1.0 - pooled) * (1.0 / n_1 + 1.0 / n_2);
+    if variance <= 0.0 {
+        return 0.0;
+    }
+    (x_1 as f64 / n_1 - x_2 as f64 / n_2) / variance.sqrt()
+}
+
+/// Barnard's unconditional exact test on the table `[[a, b], [c, d]]`.
+///
+/// Rows are treated as independent binomial samples. Tables are ordered by the pooled
+/// z statistic, and each p-value is maximised over a grid of the common proportion,
+/// then refined by golden section search between the neighbours of the best grid value.
+/// More powerful than Fisher's test, but each of the 165 proportions tried sums over all
+/// `(a + b + 1) * (c + d + 1)` tables.
+pub fn barnard_exact(a: u32, b: u32, c: u32, d: u32) -> ExactTestResult {
+    let (n_1, n_2) = (a + b, c + d);
+    if n_1 == 0 || n_2 == 0 {
+        return ExactTestResult {
+            two_sided: 1.0,
+            greater: 1.0,
+            less: 1.0,
+        };
+    }
+    let observed = pooled_z(a, n_1, c, n_2);
+    let tolerance = 1e-7 * observed.abs().max(1.0);
+    // Tails each table is in, as bits of two-sided, greater and less
+    let tails: Vec<u8> = (0..=n_1)
+        .flat_map(|x_1| (0..=n_2).map(move |x_2| pooled_z(x_1, n_1, x_2, n_2)))
+        .map(|statistic| {
+            (statistic.abs() >= observed.abs() - tolerance) as u8
+                | ((statistic >= observed - tolerance) as u8) << 1
+                | ((statistic <= observed + tolerance) as u8) << 2
+        })
+        .collect();
+    let ln_choose_1: Vec<f64> = (0..=n_1).map(|x| ln_choose(n_1, x)).collect();
+    let ln_choose_2: Vec<f64> = (0..=n_2).map(|x| ln_choose(n_2, x)).collect();
+    let p_values = |pi: f64| {
+        let (ln_pi, ln_1_pi) = (pi.ln(), (1.0 - pi).ln());
+        let pmf = |ln_choose: &[f64], n: u32| -> Vec<f64> {
+            (0..=n)
+                .map(|x| (ln_choose[x as usize] + x as f64 * ln_pi + (n - x) as f64 * ln_1_pi).exp())
+                .collect()
+        };
+        let pmf_2 = pmf(&ln_choose_2, n_2);
+        let mut p_values = [0.0; 3];
+        for (row, p_1) in tails.chunks(pmf_2.len()).zip(pmf(&ln_choose_1, n_1)) {
+            for (&in_tails, p_2) in row.iter().zip(pmf_2.iter()) {
+                for (tail, p_value) in p_values.iter_mut().enumerate() {
+                    if in_tails >> tail & 1 == 1 {
+                        *p_value += p_1 * p_2;
+                    }
+                }
+            }
+        }
+        p_values
+    };
+
+    let grid: Vec<[f64; 3]> = (1..BARNARD_GRID)
+        .map(|i| p_values(i as f64 / BARNARD_GRID as f64))
+        .collect();
+    let mut result = [0.0; 3];
+    for (tail, p_value) in result.iter_mut().enumerate() {
+        let best = (0..grid.len())
+            .max_by(|&i, &j| grid[i][tail].total_cmp(&grid[j][tail]))
+            .unwrap_or(0);
+        // Grid index i is the proportion (i + 1) / BARNARD_GRID
+        let low = best as f64 / BARNARD_GRID as f64;
+        let high = (best + 2) as f64 / BARNARD_GRID as f64;
+        let refined = golden_section_max(|pi| p_values(pi)[tail], low, high);
+        *p_value = grid[best][tail].max(refined).min(1.0);
+    }
+    ExactTestResult {
+        two_sided: result[0],
+        greater: result[1],
+        less: result[2],
+    }
+}
+
+/// Largest value of `f` found by golden section search on the open interval `low..high`,
+/// assuming `f` has a single maximum there.
+fn golden_section_max(f: impl Fn(f64) -> f64, mut low: f64, mut high: f64) -> f64 {
+    let ratio = (5f64.sqrt() - 1.0) / 2.0;
+    let mut x_1 = high - ratio * (high - low);
+    let mut x_2 = low + ratio * (high - low);
+    let (mut f_1, mut f_2) = (f(x_1), f(x_2));
+    for _ in 0..BARNARD_REFINE_STEPS {
+        if f_1 < f_2 {
+            low = x_1;
+            (x_1, f_1) = (x_2, f_2);
+            x_2 = low + ratio * (high - low);
+            f_2 = f(x_2);
+        } else {
+            high = x_2;
+            (x_2, f_2) = (x_1, f_1);
+            x_1 = high - ratio * (high - low);
+            f_1 = f(x_1);
+        }
+    }
+    f_1.max(f_2)
+}
+
+/// Bonferroni adjusted p-values, capped at 1.
+pub fn bonferroni(p_values: &[f64]) -> Vec<f64> {
+    let m = p_values.len() as f64;
+    p_values.iter().map(|p| (p * m).min(1.0)).collect()
+}
+
+/// Benjamini-Hochberg adjusted p-values controlling the false discovery rate.
+pub fn benjamini_hochberg(p_values: &[f64]) -> Vec<f64> {
+    let m = p_values.len();
+    let mut order: Vec<usize> = (0..m).collect();
+    order.sort_by(|&a, &b| p_values[b].total_cmp(&p_values[a]));
+    let mut adjusted = vec![0.0; m];
+    let mut running_min: f64 = 1.0;
+    // From the largest p-value down, keeping the adjusted values monotone
+    for (i, &idx) in order.iter().enumerate() {
+        let rank = m - i;
+        running_min = running_min.min(p_values[idx] * m as f64 / rank as f64);
+        adjusted[idx] = running_min;
+    }
+    adjusted
+}
+
+/// Storey's estimate of the proportion of true null hypotheses, from the p-values above `lambda`.
+///
+/// Without any p-value above `lambda` the estimate would be 0, giving q-values of 0, so
+/// it falls back to 1 and the q-values to the Benjamini-Hochberg values.
+pub fn storey_pi0(p_values: &[f64], lambda: f64) -> f64 {
+    let n_above = p_values.iter().filter(|&&p| p > lambda).count() as f64;
+    if n_above == 0.0 {
+        return 1.0;
+    }
+    (n_above / (p_values.len() as f64 * (1.0 - lambda))).min(1.0)
+}
+
+/// Storey q-values, the Benjamini-Hochberg values scaled by the estimated null proportion
+/// with `lambda = 0.5`.
+pub fn storey_q_values(p_values: &[f64]) -> Vec<f64> {
+    let pi0 = storey_pi0(p_values, 0.5);
+    benjamini_hochberg(p_values)
+        .into_iter()
+        .map(|q| q * pi0)
+        .collect()
+}
+
+/// Continued fraction of the incomplete beta function, evaluated with Lentz's method.
+fn incomplete_beta_fraction(x: f64, a: f64, b: f64) -> f64 {
+    let tiny = 1e-300;
+    let (mut c, mut d) = (1.0, 1.0 - (a + b) * x / (a + 1.0));
+    if d.abs() < tiny {
+        d = tiny;
+    }
+    d = 1.0 / d;
+    let mut fraction = d;
+    for m in 1..=MAX_FRACTION_TERMS {
+        let m = m as f64;
+        // Even and odd steps of the fraction
+        for numerator in [
+            m * (b - m) * x / ((a + 2.0 * m - 1.0) * (a + 2.0 * m)),
+            -(a + m) * (a + b + m) * x / ((a + 2.0 * m) * (a + 2.0 * m + 1.0)),
+        ] {
+            d = 1.0 + numerator * d;
+            if d.abs() < tiny {
+                d = tiny;
+            }
+            c = 1.0 + numerator / c;
+            if c.abs() < tiny {
+                c = tiny;
+            }
+            d = 1.0 / d;
+            fraction *= d * c;
+        }
+        if (d * c - 1.0).abs() < 1e-15 {
+            break;
+        }
+    }
+    fraction
+}
+
+/// Regularized incomplete beta function `I_x(a, b)`, the cumulative distribution
+/// function of a beta distribution.
+pub fn regularized_incomplete_beta(x: f64, a: f64, b: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+    let ln_front = ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln();
+    // The fraction converges quickly on the side of the mean
+    if x < (a + 1.0) / (a + b + 2.0) {
+        ln_front.exp() * incomplete_beta_fraction(x, a, b) / a
+    } else {
+        1.0 - ln_front.exp() * incomplete_beta_fraction(1.0 - x, b, a) / b
+    }
+}
+
+/// Regularized upper incomplete gamma function `Q(a, x)`.
+fn regularized_upper_gamma(a: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 1.0;
+    }
+    let ln_front = -x + a * x.ln() - ln_gamma(a);
+    if x < a + 1.0 {
+        // Series of the lower function
+        let (mut term, mut sum, mut n) = (1.0 / a, 1.0 / a, a);
+        for _ in 0..MAX_FRACTION_TERMS {
+            n += 1.0;
+            term *= x / n;
+            sum += term;
+            if term.abs() < sum.abs() * 1e-15 {
+                break;
+            }
+        }
+        1.0 - sum * ln_front.exp()
+    } else {
+        // Continued fraction of the upper function, evaluated with Lentz's method
+        let tiny = 1e-300;
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / tiny;
+        let mut d = 1.0 / b;
+        let mut fraction = d;
+        for i in 1..=MAX_FRACTION_TERMS {
+            let numerator = -(i as f64) * (i as f64 - a);
+            b += 2.0;
+            d = numerator * d + b;
+            if d.abs() < tiny {
+                d = tiny;
+            }
+            c = b + numerator / c;
+            if c.abs() < tiny {
+                c = tiny;
+            }
+            d = 1.0 / d;
+            fraction *= d * c;
+            if (d * c - 1.0).abs() < 1e-15 {
+                break;
+            }
+        }
+        ln_front.exp() * fraction
+    }
+}
+
+/// Upper tail probability of a chi-squared distribution with `df` degrees of freedom.
+pub fn chi_squared_sf(x: f64, df: usize) -> f64 {
+    if df == 0 {
+        return f64::NAN;
+    }
+    regularized_upper_gamma(df as f64 / 2.0, x / 2.0)
+}
+
+/// Mantel-Haenszel estimate of the common odds ratio of 2x2 tables `[a, b, c, d]`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MantelHaenszel {
+    pub odds_ratio: f64,
+    /// Robins-Breslow-Greenland variance of the log odds ratio.
+    pub log_odds_ratio_var: f64,
+}
+
+impl MantelHaenszel {
+    pub fn new(tables: &[[u32; 4]]) -> Self {
+        Self::from_cells(tables.iter().map(|table| table.map(|n| n as f64)))
+    }
+
+    /// Estimate with 0.5 added to every cell as in the Haldane-Anscombe correction, finite
+    /// even when every table has an empty cell in the same place.
+    pub fn haldane_anscombe(tables: &[[u32; 4]]) -> Self {
+        Self::from_cells(tables.iter().map(|table| table.map(|n| n as f64 + 0.5)))
+    }
+
+    /// Whether the odds ratio and its variance are finite and the odds ratio is positive.
+    pub fn is_estimable(&self) -> bool {
+        self.odds_ratio > 0.0 && self.odds_ratio.is_finite() && self.log_odds_ratio_var.is_finite()
+    }
+
+    fn from_cells(tables: impl Iterator<Item = [f64; 4]>) -> Self {
+        let (mut r, mut s) = (0.0, 0.0);
+        let (mut pr, mut ps_qr, mut qs) = (0.0, 0.0, 0.0);
+        for [a, b, c, d] in tables {
+            let n = a + b + c + d;
+            if n == 0.0 {
+                continue;
+            }
+            let (r_i, s_i) = (a * d / n, b * c / n);
+            let (p_i, q_i) = ((a + d) / n, (b + c) / n);
+            r += r_i;
+            s += s_i;
+            pr += p_i * r_i;
+            ps_qr += p_i * s_i + q_i * r_i;
+            qs += q_i * s_i;
+        }
+        Self {
+            odds_ratio: r / s,
+            log_odds_ratio_var: pr / (2.0 * r * r) + ps_qr / (2.0 * r * s) + qs / (2.0 * s * s),
+        }
+    }
+
+    pub fn confidence_interval(&self) -> ConfidenceInterval {
+        ConfidenceInterval::wald(self.odds_ratio.ln(), self.log_odds_ratio_var).map(f64::exp)
+    }
+}
+
+/// Breslow-Day test of the homogeneity of the odds ratios of 2x2 tables `[a, b, c, d]`,
+/// given their common odds ratio.
+///
+/// Gives the statistic and its degrees of freedom, one less than the number of tables
+/// whose margins leave `a` free to vary.
+pub fn breslow_day(tables: &[[u32; 4]], odds_ratio: f64) -> (f64, usize) {
+    if !(odds_ratio > 0.0 && odds_ratio.is_finite()) {
+        return (f64::NAN, 0);
+    }
+    let (mut statistic, mut n_tables) = (0.0, 0usize);
+    for table in tables.iter() {
+        let [a, b, c, d] = table.map(|n| n as f64);
+        let (row_1, row_2, col_1) = (a + b, c + d, a + c);
+        let (min_a, max_a) = ((col_1 - row_2).max(0.0), row_1.min(col_1));
+        if max_a <= min_a {
+            continue;
+        }
+        // Expected a under the common odds ratio, the root of a quadratic within the margins
+        let expected = if (odds_ratio - 1.0).abs() < 1e-12 {
+            row_1 * col_1 / (row_1 + row_2)
+        } else {
+            let qa = 1.0 - odds_ratio;
+            let qb = row_2 - col_1 + odds_ratio * (row_1 + col_1);
+            let qc = -odds_ratio * row_1 * col_1;
+            let root = (qb * qb - 4.0 * qa * qc).sqrt();
+            let x = (-qb + root) / (2.0 * qa);
+            if (min_a..=max_a).contains(&x) {
+                x
+            } else {
+                (-qb - root) / (2.0 * qa)
+            }
+        };
+        let variance = 1.0
+            / (1.0 / expected
+                + 1.0 / (row_1 - expected)
+                + 1.0 / (col_1 - expected)
+                + 1.0 / (row_2 - col_1 + expected));
+        statistic += (a - expected).powi(2) / variance;
+        n_tables += 1;
+    }
+    (statistic, n_tables.saturating_sub(1))
+}
+
+/// Two-sided 95% confidence interval.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConfidenceInterval {
+    pub low: f64,
+    pub high: f64,
+}
+
+impl ConfidenceInterval {
+    /// Wald interval of an estimate with the given variance.
+    pub fn wald(estimate: f64, variance: f64) -> Self {
+        let margin = Z_95 * variance.sqrt();
+        Self {
+            low: estimate - margin,
+            high: estimate + margin,
+        }
+    }
+
+    /// Interval with `f` applied to both bounds, for monotone increasing `f`.
+    pub fn map(&self, f: impl Fn(f64) -> f64) -> Self {
+        Self {
+            low: f(self.low),
+            high: f(self.high),
+        }
+    }
+}
+
+/// 95% percentile interval of bootstrap replicates, ignoring `NaN` replicates and
+/// interpolating linearly between order statistics.
+pub fn percentile_interval(replicates: &[f64]) -> ConfidenceInterval {
+    let mut values: Vec<f64> = replicates.iter().copied().filter(|x| !x.is_nan()).collect();
+    if values.is_empty() {
+        return ConfidenceInterval {
+            low: f64::NAN,
+            high: f64::NAN,
+        };
+    }
+    values.sort_by(|a, b| a.total_cmp(b));
+    let quantile = |q: f64| {
+        let rank = q * (values.len() - 1) as f64;
+        let (below, above) = (rank.floor() as usize, rank.ceil() as usize);
+        if below == above {
+            values[below]
+        } else {
+            values[below] + (rank - below as f64) * (values[above] - values[below])
+        }
+    };
+    ConfidenceInterval {
+        low: quantile(TAIL_95),
+        high: quantile(1.0 - TAIL_95),
+    }
+}
+
+/// Haldane-Anscombe corrected log odds ratio of the table `[[a, b], [c, d]]` and its
+/// variance, adding 0.5 to every cell so that both stay finite with empty cells.
+pub fn haldane_anscombe_log_odds_ratio(a: u32, b: u32, c: u32, d: u32) -> (f64, f64) {
+    let [a, b, c, d] = [a, b, c, d].map(|n| n as f64 + 0.5);
+    ((a * d / (b * c)).ln(), 1.0 / a + 1.0 / b + 1.0 / c + 1.0 / d)
+}
+
+/// Finds where the monotone `f` crosses `target` on `low..high` by bisection.
+fn bisect(f: impl Fn(f64) -> f64, target: f64, mut low: f64, mut high: f64, increasing: bool) -> f64 {
+    for _ in 0..BISECTION_STEPS {
+        let mid = 0.5 * (low + high);
+        if (f(mid) < target) == increasing {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    0.5 * (low + high)
+}
+
+/// Mid-p tail probabilities `P(X > x) + P(X = x) / 2` and `P(X < x) + P(X = x) / 2` of
+/// the distribution with the given log weights over `0..`, normalised here.
+fn mid_p_tails(ln_weights: &[f64], x: usize) -> (f64, f64) {
+    let max = ln_weights.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let weights: Vec<f64> = ln_weights.iter().map(|w| (w - max).exp()).collect();
+    let total: f64 = weights.iter().sum();
+    let below: f64 = weights[..x].iter().sum();
+    let above: f64 = weights[x + 1..].iter().sum();
+    let half = 0.5 * weights[x];
+    ((above + half) / total, (below + half) / total)
+}
+
+/// Mid-p exact 95% confidence interval of a binomial proportion with `x` successes in
+/// `n` trials.
+pub fn mid_p_proportion_interval(x: u32, n: u32) -> ConfidenceInterval {
+    if n == 0 {
+        return ConfidenceInterval { low: 0.0, high: 1.0 };
+    }
+    let ln_choose: Vec<f64> = (0..=n).map(|k| ln_choose(n, k)).collect();
+    let tails = |p: f64| {
+        let ln_weights: Vec<f64> = (0..=n)
+            .map(|k| ln_choose[k as usize] + k as f64 * p.ln() + (n - k) as f64 * (1.0 - p).ln())
+            .collect();
+        mid_p_tails(&ln_weights, x as usize)
+    };
+    let low = if x == 0 {
+        0.0
+    } else {
+        bisect(|p| tails(p).0, TAIL_95, 0.0, 1.0, true)
+    };
+    let high = if x == n {
+        1.0
+    } else {
+        bisect(|p| tails(p).1, TAIL_95, 0.0, 1.0, false)
+    };
+    ConfidenceInterval { low, high }
+}
+
+/// Mid-p exact 95% confidence interval of the odds ratio of the table `[[a, b], [c, d]]`,
+/// inverting the conditional test on the noncentral hypergeometric distribution.
+pub fn mid_p_odds_ratio_interval(a: u32, b: u32, c: u32, d: u32) -> ConfidenceInterval {
+    let (row_1, row_2, col_1) = (a + b, c + d, a + c);
+    let min_x = col_1.saturating_sub(row_2);
+    let max_x = row_1.min(col_1);
+    let ln_weights: Vec<f64> = (min_x..=max_x)
+        .map(|x| ln_choose(row_1, x) + ln_choose(row_2, col_1 - x))
+        .collect();
+    let x = (a - min_x) as usize;
+    // Tails at odds ratio exp(ln_psi)
+    let tails = |ln_psi: f64| {
+        let shifted: Vec<f64> = ln_weights
+            .iter()
+            .enumerate()
+            .map(|(i, w)| w + i as f64 * ln_psi)
+            .collect();
+        mid_p_tails(&shifted, x)
+    };
+    let (ln_min, ln_max) = (-50.0, 50.0);
+    let low = if a == min_x {
+        0.0
+    } else {
+        bisect(|ln_psi| tails(ln_psi).0, TAIL_95, ln_min, ln_max, true).exp()
+    };
+    let high = if a == max_x {
+        f64::INFINITY
+    } else {
+        bisect(|ln_psi| tails(ln_psi).1, TAIL_95, ln_min, ln_max, false).exp()
+    };
+    ConfidenceInterval { low, high }
+}
+
+/// Wald 95% confidence interval of the difference in proportions `x_1 / n_1 - x_2 / n_2`
+/// with the Agresti-Caffo adjustment of one success and one failure added to each sample.
+pub fn difference_wald_interval(x_1: u32, n_1: u32, x_2: u32, n_2: u32) -> ConfidenceInterval {
+    let p_1 = (x_1 as f64 + 1.0) / (n_1 as f64 + 2.0);
+    let p_2 = (x_2 as f64 + 1.0) / (n_2 as f64 + 2.0);
+    let variance = p_1 * (1.0 - p_1) / (n_1 as f64 + 2.0) + p_2 * (1.0 - p_2) / (n_2 as f64 + 2.0);
+    ConfidenceInterval::wald(p_1 - p_2, variance).map(|bound| bound.clamp(-1.0, 1.0))
+}
+
+/// 95% confidence interval of the difference in proportions `x_1 / n_1 - x_2 / n_2`,
+/// combining the mid-p intervals of both proportions with Newcombe's square-and-add method.
+pub fn difference_mid_p_interval(x_1: u32, n_1: u32, x_2: u32, n_2: u32) -> ConfidenceInterval {
+    let p_1 = if n_1 == 0 { 0.5 } else { x_1 as f64 / n_1 as f64 };
+    let p_2 = if n_2 == 0 { 0.5 } else { x_2 as f64 / n_2 as f64 };
+    let interval_1 = mid_p_proportion_interval(x_1, n_1);
+    let interval_2 = mid_p_proportion_interval(x_2, n_2);
+    let difference = p_1 - p_2;
+    ConfidenceInterval {
+        low: difference
+            - ((p_1 - interval_1.low).powi(2) + (interval_2.high - p_2).powi(2)).sqrt(),
+        high: difference
+            + ((interval_1.high - p_1).powi(2) + (p_2 - interval_2.low).powi(2)).sqrt(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f64, b: f64, tolerance: f64) {
+        assert!((a - b).abs() < tolerance, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn test_ln_gamma() {
+        assert_close(ln_gamma(1.0), 0.0, 1e-12);
+        assert_close(ln_gamma(5.0), 24f64.ln(), 1e-12);
+        assert_close(ln_gamma(0.5), std::f64::consts::PI.sqrt().ln(), 1e-12);
+        assert_close(ln_choose(10, 3), 120f64.ln(), 1e-10);
+        assert_eq!(ln_choose(3, 4), f64::NEG_INFINITY);
+    }
+
+    #[test]
+    fn test_fisher_exact() {
+        // Reference values computed with exact rational arithmetic
+        let result = fisher_exact(1, 9, 11, 3);
+        assert_close(result.two_sided, 0.002_759_456_185_220_7, 1e-12);
+        assert_close(result.less, 0.001_379_728_092_610_4, 1e-12);
+        assert_close(result.greater, 0.999_966_348_095_708_5, 1e-12);
+
+        // Fully methylated against unmethylated strand
+        let result = fisher_exact(10, 0, 0, 10);
+        assert_close(result.two_sided, 1.082_508_822_446_903e-5, 1e-15);
+
+        let result = fisher_exact(5, 5, 5, 5);
+        assert_close(result.two_sided, 1.0, 1e-12);
+        assert_eq!(fisher_exact(0, 0, 0, 0).two_sided, 1.0);
+    }
+
+    #[test]
+    fn test_barnard_exact() {
+        // Barnard's test is more powerful than Fisher's on the same table
+        let fisher = fisher_exact(7, 5, 1, 11);
+        let barnard = barnard_exact(7, 5, 1, 11);
+        assert!(barnard.two_sided < fisher.two_sided);
+        // Reference values computed on a 20000 point grid
+        assert_close(barnard.two_sided, 0.010_278_681, 1e-9);
+        assert_close(barnard_exact(3, 17, 9, 11).two_sided, 0.049_770_508, 1e-9);
+        assert!(barnard.greater < barnard.less);
+
+        let result = barnard_exact(5, 5, 5, 5);
+        assert_close(result.two_sided, 1.0, 1e-9);
+        assert_eq!(barnard_exact(0, 0, 3, 3).two_sided, 1.0);
+    }
+
+    #[test]
+    fn test_multiple_testing_correction() {
+        let p_values = [0.01, 0.04, 0.03, 0.2];
+        assert_eq!(bonferroni(&p_values), vec![0.04, 0.16, 0.12, 0.8]);
+        // Same as p.adjust(method = "BH") in R
+        let adjusted = benjamini_hochberg(&p_values);
+        for (a, b) in adjusted.iter().zip([0.04, 0.16 / 3.0, 0.16 / 3.0, 0.2]) {
+            assert_close(*a, b, 1e-12);
+        }
+        assert!(benjamini_hochberg(&[]).is_empty());
+
+        // Two of six p-values above 0.5 give an estimated null proportion of 2/3
+        let p_values = [0.001, 0.002, 0.01, 0.02, 0.6, 0.9];
+        assert_close(storey_pi0(&p_values, 0.5), 2.0 / 3.0, 1e-12);
+        let q_values = storey_q_values(&p_values);
+        assert_close(q_values[0], 2.0 / 3.0 * 0.006, 1e-12);
+        assert_close(storey_pi0(&[0.9, 0.8], 0.5), 1.0, 1e-12);
+
+        // No p-value above 0.5 falls back to the Benjamini-Hochberg values
+        let p_values = [0.001, 0.01, 0.2];
+        assert_eq!(storey_pi0(&p_values, 0.5), 1.0);
+        assert_eq!(storey_q_values(&p_values), benjamini_hochberg(&p_values));
+        assert_eq!(storey_pi0(&[], 0.5), 1.0);
+    }
+
+    #[test]
+    fn test_haldane_anscombe_log_odds_ratio() {
+        let (log_odds_ratio, variance) = haldane_anscombe_log_odds_ratio(10, 0, 0, 10);
+        assert_close(log_odds_ratio, (10.5f64 * 10.5 / 0.25).ln(), 1e-12);
+        assert_close(variance, 2.0 / 10.5 + 4.0, 1e-12);
+        let interval = ConfidenceInterval::wald(log_odds_ratio, variance).map(f64::exp);
+        assert!(interval.low > 1.0 && interval.high.is_finite());
+    }
+
+    #[test]
+    fn test_mid_p_intervals() {
+        // P(X = 0) / 2 = 0.025 gives the upper bound 1 - 0.05^(1/10) when x = 0
+        let interval = mid_p_proportion_interval(0, 10);
+        assert_eq!(interval.low, 0.0);
+        assert_close(interval.high, 1.0 - 0.05f64.powf(0.1), 1e-9);
+        let interval = mid_p_proportion_interval(10, 10);
+        assert_close(interval.low, 0.05f64.powf(0.1), 1e-9);
+        assert_eq!(interval.high, 1.0);
+        let interval = mid_p_proportion_interval(5, 10);
+        assert_close(interval.low, 1.0 - interval.high, 1e-9);
+
+        // With one read per strand P(X = 1) = psi / (1 + psi), halved at the lower bound
+        let interval = mid_p_odds_ratio_interval(1, 0, 0, 1);
+        assert_close(interval.low, 0.05 / 0.95, 1e-9);
+        assert_eq!(interval.high, f64::INFINITY);
+        let interval = mid_p_odds_ratio_interval(0, 10, 10, 0);
+        assert_eq!(interval.low, 0.0);
+        assert!(interval.high < 1.0);
+        let interval = mid_p_odds_ratio_interval(5, 5, 5, 5);
+        assert_close(interval.low * interval.high, 1.0, 1e-9);
+    }
+
+    #[test]
+    fn test_difference_intervals() {
+        let wald = difference_wald_interval(10, 10, 0, 10);
+        assert!(wald.low > 0.0 && wald.high <= 1.0);
+        let wald = difference_wald_interval(5, 10, 5, 10);
+        assert_close(wald.low, -wald.high, 1e-12);
+
+        let mid_p = difference_mid_p_interval(10, 10, 0, 10);
+        assert_close(mid_p.low, 1.0 - 2f64.sqrt() * (1.0 - 0.05f64.powf(0.1)), 1e-9);
+        assert_eq!(mid_p.high, 1.0);
+        let mid_p = difference_mid_p_interval(0, 0, 0, 0);
+        assert!(mid_p.low < 0.0 && mid_p.high > 0.0);
+    }
+
+    #[test]
+    fn test_regularized_incomplete_beta() {
+        assert_close(regularized_incomplete_beta(0.3, 1.0, 1.0), 0.3, 1e-12);
+        assert_close(regularized_incomplete_beta(0.3, 4.0, 1.0), 0.3f64.powi(4), 1e-12);
+        assert_close(regularized_incomplete_beta(0.5, 7.5, 7.5), 0.5, 1e-12);
+        // I_x(2, 3) = 6x^2 - 8x^3 + 3x^4
+        let x: f64 = 0.8;
+        assert_close(
+            regularized_incomplete_beta(x, 2.0, 3.0),
+            6.0 * x.powi(2) - 8.0 * x.powi(3) + 3.0 * x.powi(4),
+            1e-12,
+        );
+        assert_eq!(regularized_incomplete_beta(0.0, 2.0, 3.0), 0.0);
+        assert_eq!(regularized_incomplete_beta(1.0, 2.0, 3.0), 1.0);
+    }
+
+    #[test]
+    fn test_chi_squared_sf() {
+        assert_close(chi_squared_sf(3.841_458_820_694_124, 1), 0.05, 1e-12);
+        // Exponential with mean 2 for two degrees of freedom
+        assert_close(chi_squared_sf(5.0, 2), (-2.5f64).exp(), 1e-12);
+        assert_close(chi_squared_sf(0.5, 2), (-0.25f64).exp(), 1e-12);
+        assert_eq!(chi_squared_sf(0.0, 3), 1.0);
+        assert!(chi_squared_sf(1.0, 0).is_nan());
+    }
+
+    #[test]
+    fn test_mantel_haenszel() {
+        // Tables with the same odds ratio of 4
+        let tables = [[4, 2, 2, 4], [8, 1, 2, 1], [2, 2, 1, 4]];
+        let pooled = MantelHaenszel::new(&tables);
+        assert_close(pooled.odds_ratio, 4.0, 1e-12);
+        let interval = pooled.confidence_interval();
+        assert!(interval.low < 4.0 && interval.high > 4.0);
+        let (statistic, df) = breslow_day(&tables, pooled.odds_ratio);
+        assert_close(statistic, 0.0, 1e-12);
+        assert_eq!(df, 2);
+
+        // Opposite odds ratios average out but are heterogeneous
+        let tables = [[9, 1, 1, 9], [1, 9, 9, 1]];
+        let pooled = MantelHaenszel::new(&tables);
+        assert_close(pooled.odds_ratio, 1.0, 1e-12);
+        let (statistic, df) = breslow_day(&tables, pooled.odds_ratio);
+        assert_eq!(df, 1);
+        assert!(chi_squared_sf(statistic, df) < 1e-4);
+
+        // Only modified reads leave no free cell
+        assert_eq!(breslow_day(&[[1, 0, 1, 0]], 2.0), (0.0, 0));
+        assert!(breslow_day(&tables, f64::INFINITY).0.is_nan());
+    }
+
+    #[test]
+    fn test_percentile_interval() {
+        let replicates: Vec<f64> = (0..=100).map(|i| i as f64).collect();
+        let interval = percentile_interval(&replicates);
+        assert_close(interval.low, 2.5, 1e-12);
+        assert_close(interval.high, 97.5, 1e-12);
+        let interval = percentile_interval(&[f64::NAN, 1.0, 3.0]);
+        assert_close(interval.low, 1.05, 1e-12);
+        assert_close(interval.high, 2.95, 1e-12);
+        assert!(percentile_interval(&[f64::NAN]).low.is_nan());
+    }
+}
@@ -10,15 +10,9 @@ pub enum Strand {
 
 impl Display for Strand {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.to_string())
-    }
-}
-
-impl Strand {
-    pub fn to_string(&self) -> String {
         match self {
-            Strand::Positive => "+".to_string(),
-            Strand::Negative => "-".to_string(),
+            Strand::Positive => write!(f, "+"),
+            Strand::Negative => write!(f, "-"),
         }
     }
 }